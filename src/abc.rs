@@ -0,0 +1,644 @@
+//// Approximate Bayesian Computation
+// The `abc` subcommand fits parameters to observed trajectories of adult
+// genotype frequencies, each given as `generation,AA,AB,BB` rows of counts
+// or proportions. Parameters have uniform priors within ranges. Every
+// parameter set is simulated once per observed trajectory and compared to
+// it by the Euclidean distance between summary statistics at observed
+// generations: the frequency of A, the proportion of heterozygotes or the
+// three genotype proportions. A simulation that collapses, without males,
+// before the last observed generation is at an infinite distance and never
+// accepted. Rejection keeps the closest parameter sets
+// among draws from the prior. SMC, the population Monte Carlo sampler of
+// Beaumont et al. (2009), moves weighted particles through rounds of
+// decreasing tolerance, each tolerance being a quantile of the distances
+// of the previous round
+use clap::ArgMatches;
+use rand::distributions::{Distribution, Normal, WeightedIndex};
+use rand::prelude::*;
+
+use std::f64::consts::PI;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::process;
+use std::str::FromStr;
+
+use crate::parameters::Parameters;
+use crate::random::{self, SimulationRng};
+use crate::sensitivity::Range;
+use crate::simulation::{self, RunSummary};
+
+//// Enums
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Method {
+    Rejection,
+    Smc,
+}
+
+impl FromStr for Method {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rejection" => Ok(Method::Rejection),
+            "smc" => Ok(Method::Smc),
+            _ => Err(format!("Unknown ABC method: {}", s)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Statistic {
+    Frequency,
+    Heterozygosity,
+    Genotypes,
+}
+
+impl FromStr for Statistic {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "frequency" => Ok(Statistic::Frequency),
+            "heterozygosity" => Ok(Statistic::Heterozygosity),
+            "genotypes" => Ok(Statistic::Genotypes),
+            _ => Err(format!("Unknown summary statistic: {}", s)),
+        }
+    }
+}
+
+//// Structs
+pub struct Trajectory {
+    pub generations: Vec<u32>,
+    pub counts: Vec<[f64; 3]>,
+    pub proportions: Vec<[f64; 3]>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Particle {
+    pub values: Vec<f64>,
+    pub distance: f64,
+    pub weight: f64,
+}
+
+pub struct Abc {
+    base: Parameters,
+    priors: Vec<Range>,
+    observed: Vec<Trajectory>,
+    statistics: Vec<Statistic>,
+}
+
+impl Abc {
+    fn summary_statistics(&self, proportions: &[f64; 3]) -> Vec<f64> {
+        let mut values = Vec::new();
+
+        for statistic in self.statistics.iter() {
+            match statistic {
+                Statistic::Frequency => values.push(proportions[0] + proportions[1] / 2.0),
+                Statistic::Heterozygosity => values.push(proportions[1]),
+                Statistic::Genotypes => values.extend_from_slice(proportions),
+            }
+        }
+
+        values
+    }
+
+    fn trajectory_distance(&self, observed: &Trajectory, simulated: &RunSummary) -> f64 {
+        // Squared distance over observed generations. Generations after the
+        // end of a simulation take its last state, unless the simulated
+        // population collapsed, which cannot explain later observations
+        let simulated_generations = simulated.adult_proportions.len();
+
+        if simulated.collapsed
+            && observed
+                .generations
+                .iter()
+                .any(|g| *g as usize >= simulated_generations)
+        {
+            return f64::INFINITY;
+        }
+
+        let last = simulated
+            .adult_proportions
+            .last()
+            .cloned()
+            .unwrap_or([0.0; 3]);
+        let mut distance = 0.0;
+
+        for (generation, proportions) in
+            observed.generations.iter().zip(observed.proportions.iter())
+        {
+            let simulated_proportions = simulated
+                .adult_proportions
+                .get(*generation as usize)
+                .cloned()
+                .unwrap_or(last);
+
+            let expected = self.summary_statistics(proportions);
+            let obtained = self.summary_statistics(&simulated_proportions);
+
+            distance += expected
+                .iter()
+                .zip(obtained.iter())
+                .map(|(e, o)| (e - o).powi(2))
+                .sum::<f64>();
+        }
+
+        distance
+    }
+
+    pub fn distance(&self, values: &[f64], evaluation: u64) -> f64 {
+        // Distance between observed trajectories and one simulation of
+        // each with the given parameter values, seeded by the number of
+        // the evaluation
+        let mut params = self.base.clone();
+
+        for (prior, value) in self.priors.iter().zip(values.iter()) {
+            if let Err(e) = params.set(&prior.name, *value) {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+
+        let number_observed = self.observed.len() as u64;
+
+        self.observed
+            .iter()
+            .enumerate()
+            .map(|(i, observed)| {
+                params.seed =
+                    random::run_seed(self.base.seed, evaluation * number_observed + i as u64);
+                let simulated = simulation::run_summary(&params);
+                self.trajectory_distance(observed, &simulated)
+            })
+            .sum::<f64>()
+            .sqrt()
+    }
+
+    fn sample_prior(&self, rng: &mut SimulationRng) -> Vec<f64> {
+        self.priors
+            .iter()
+            .map(|p| p.low + rng.gen::<f64>() * (p.high - p.low))
+            .collect()
+    }
+
+    fn in_prior(&self, values: &[f64]) -> bool {
+        self.priors
+            .iter()
+            .zip(values.iter())
+            .all(|(p, v)| *v >= p.low && *v <= p.high)
+    }
+
+    pub fn rejection(
+        &self,
+        number_simulations: usize,
+        number_accepted: usize,
+        rng: &mut SimulationRng,
+    ) -> Vec<Particle> {
+        // Keep the parameter sets closest to the observations
+        let particles: Vec<Particle> = (0..number_simulations)
+            .map(|evaluation| {
+                let values = self.sample_prior(rng);
+                let distance = self.distance(&values, evaluation as u64);
+
+                Particle {
+                    values,
+                    distance,
+                    weight: 1.0,
+                }
+            })
+            .collect();
+
+        select_closest(particles, number_accepted)
+    }
+
+    pub fn smc(
+        &self,
+        number_particles: usize,
+        number_rounds: usize,
+        quantile: f64,
+        max_simulations: usize,
+        rng: &mut SimulationRng,
+    ) -> Vec<Particle> {
+        // Population Monte Carlo through rounds of decreasing tolerance. The
+        // first round is a rejection step keeping `number_particles` out of
+        // `number_particles / quantile` draws from the prior
+        let first_draws = (number_particles as f64 / quantile).ceil() as usize;
+        let mut particles = self.rejection(first_draws, number_particles, rng);
+        let mut evaluation = first_draws as u64;
+
+        if particles.is_empty() {
+            return particles;
+        }
+
+        for round in 1..number_rounds {
+            let tolerance = distance_quantile(&particles, quantile);
+
+            // Gaussian perturbation with twice the weighted variance of
+            // each parameter
+            let sigmas: Vec<f64> = (0..self.priors.len())
+                .map(|i| (2.0 * weighted_variance(&particles, i)).sqrt())
+                .collect();
+            let previous = WeightedIndex::new(particles.iter().map(|p| p.weight)).unwrap();
+
+            let mut accepted = Vec::new();
+            let mut number_simulations = 0;
+
+            while accepted.len() < number_particles {
+                if number_simulations == max_simulations {
+                    eprintln!(
+                        "ABC round {} stopped after {} simulations with {} particles",
+                        round + 1,
+                        max_simulations,
+                        accepted.len()
+                    );
+                    break;
+                }
+
+                let parent = &particles[previous.sample(rng)];
+                let values: Vec<f64> = parent
+                    .values
+                    .iter()
+                    .zip(sigmas.iter())
+                    .map(|(v, sigma)| {
+                        if *sigma > 0.0 {
+                            Normal::new(*v, *sigma).sample(rng)
+                        } else {
+                            *v
+                        }
+                    })
+                    .collect();
+
+                if !self.in_prior(&values) {
+                    continue;
+                }
+
+                number_simulations += 1;
+                let distance = self.distance(&values, evaluation);
+                evaluation += 1;
+
+                if distance <= tolerance {
+                    // Uniform prior over kernel density from the previous
+                    // particles
+                    let density: f64 = particles
+                        .iter()
+                        .map(|p| p.weight * kernel_density(&p.values, &values, &sigmas))
+                        .sum();
+
+                    accepted.push(Particle {
+                        values,
+                        distance,
+                        weight: 1.0 / density,
+                    });
+                }
+            }
+
+            if accepted.is_empty() {
+                break;
+            }
+
+            normalize_weights(&mut accepted);
+            particles = accepted;
+        }
+
+        particles
+    }
+}
+
+//// Functions
+fn select_closest(mut particles: Vec<Particle>, number_accepted: usize) -> Vec<Particle> {
+    // The `number_accepted` particles of smallest distance, with equal
+    // weights. Collapsed simulations, at an infinite distance, are never
+    // accepted
+    particles.retain(|p| p.distance.is_finite());
+    particles.sort_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap());
+    particles.truncate(number_accepted.max(1));
+
+    for p in particles.iter_mut() {
+        p.weight = 1.0;
+    }
+
+    normalize_weights(&mut particles);
+    particles
+}
+
+fn normalize_weights(particles: &mut [Particle]) {
+    let total: f64 = particles.iter().map(|p| p.weight).sum();
+
+    for p in particles.iter_mut() {
+        p.weight /= total;
+    }
+}
+
+fn distance_quantile(particles: &[Particle], quantile: f64) -> f64 {
+    let mut distances: Vec<f64> = particles.iter().map(|p| p.distance).collect();
+    distances.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let position = (quantile * (distances.len() - 1) as f64).round() as usize;
+    distances[position]
+}
+
+fn weighted_variance(particles: &[Particle], i: usize) -> f64 {
+    let mean: f64 = particles.iter().map(|p| p.weight * p.values[i]).sum();
+    particles
+        .iter()
+        .map(|p| p.weight * (p.values[i] - mean).powi(2))
+        .sum()
+}
+
+fn kernel_density(from: &[f64], to: &[f64], sigmas: &[f64]) -> f64 {
+    // Product of independent normal densities, ignoring fixed parameters
+    from.iter()
+        .zip(to.iter())
+        .zip(sigmas.iter())
+        .filter(|(_, sigma)| **sigma > 0.0)
+        .map(|((f, t), sigma)| {
+            (-(t - f).powi(2) / (2.0 * sigma * sigma)).exp() / (sigma * (2.0 * PI).sqrt())
+        })
+        .product()
+}
+
+pub fn read_trajectory(path: &str) -> Trajectory {
+    // Read `generation,AA,AB,BB` rows, ignoring comments. Counts are also
+    // kept as proportions
+    let content = fs::read_to_string(path).unwrap_or_else(|_| {
+        eprintln!("Cannot read observed trajectory: {}", path);
+        process::exit(1);
+    });
+
+    let mut trajectory = Trajectory {
+        generations: Vec::new(),
+        counts: Vec::new(),
+        proportions: Vec::new(),
+    };
+
+    for line in content.lines() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let values: Vec<&str> = line.split(',').map(|v| v.trim()).collect();
+
+        if values.len() != 4 {
+            eprintln!("Observed trajectory rows need 4 values: {}", line);
+            process::exit(1);
+        }
+
+        let generation = values[0]
+            .parse::<u32>()
+            .expect("Cannot parse observed generation");
+        let mut counts = [0.0; 3];
+
+        for (c, v) in counts.iter_mut().zip(values[1..].iter()) {
+            *c = v.parse::<f64>().expect("Cannot parse observed genotype");
+        }
+
+        let total: f64 = counts.iter().sum();
+
+        if total <= 0.0 {
+            eprintln!("Observed genotypes sum to zero: {}", line);
+            process::exit(1);
+        }
+
+        let mut proportions = counts;
+
+        for p in proportions.iter_mut() {
+            *p /= total;
+        }
+
+        trajectory.generations.push(generation);
+        trajectory.counts.push(counts);
+        trajectory.proportions.push(proportions);
+    }
+
+    trajectory
+}
+
+pub fn main(base: &Parameters, matches: &ArgMatches) {
+    // Fit parameters to observed trajectories and write accepted particles
+    let priors: Vec<Range> = matches
+        .values_of("prior")
+        .map(|values| values.collect::<Vec<&str>>())
+        .unwrap_or_default()
+        .iter()
+        .map(|v| {
+            let prior = v.parse::<Range>().unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                process::exit(1);
+            });
+
+            if let Err(e) = base.get(&prior.name) {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+
+            prior
+        })
+        .collect();
+
+    let observed: Vec<Trajectory> = matches
+        .values_of("observed")
+        .map(|values| values.map(read_trajectory).collect())
+        .unwrap_or_default();
+
+    if priors.is_empty() || observed.is_empty() {
+        eprintln!("ABC needs at least one --prior and one --observed trajectory");
+        process::exit(1);
+    }
+
+    for trajectory in observed.iter() {
+        if let Some(g) = trajectory
+            .generations
+            .iter()
+            .find(|g| **g > base.number_generations)
+        {
+            eprintln!("Observed generation {} is after --number-generations", g);
+            process::exit(1);
+        }
+    }
+
+    let statistics: Vec<Statistic> = matches
+        .value_of("statistics")
+        .unwrap_or("frequency,heterozygosity")
+        .split(',')
+        .map(|s| {
+            s.trim().parse::<Statistic>().unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                process::exit(1);
+            })
+        })
+        .collect();
+
+    let method = matches
+        .value_of("method")
+        .unwrap_or("rejection")
+        .parse::<Method>()
+        .unwrap();
+
+    let number_simulations = matches
+        .value_of("simulations")
+        .unwrap_or("1000")
+        .parse::<usize>()
+        .unwrap();
+
+    let number_particles = matches
+        .value_of("particles")
+        .unwrap_or("100")
+        .parse::<usize>()
+        .unwrap();
+
+    let number_rounds = matches
+        .value_of("rounds")
+        .unwrap_or("5")
+        .parse::<usize>()
+        .unwrap();
+
+    let quantile = matches
+        .value_of("quantile")
+        .unwrap_or("0.5")
+        .parse::<f64>()
+        .unwrap();
+
+    if quantile <= 0.0 || quantile > 1.0 {
+        eprintln!("ABC quantile must be in (0, 1]: {}", quantile);
+        process::exit(1);
+    }
+
+    let abc = Abc {
+        base: base.clone(),
+        priors,
+        observed,
+        statistics,
+    };
+
+    let mut rng = SimulationRng::new(base.seed);
+    let particles = match method {
+        Method::Rejection => abc.rejection(number_simulations, number_particles, &mut rng),
+        Method::Smc => abc.smc(
+            number_particles,
+            number_rounds,
+            quantile,
+            number_simulations,
+            &mut rng,
+        ),
+    };
+
+    if particles.is_empty() {
+        eprintln!("ABC accepted no parameter set, every simulated population collapsed");
+    }
+
+    // Write accepted parameter sets
+    let mut outfile: Box<dyn Write> = match matches.value_of("output") {
+        Some(path) => Box::new(File::create(path).expect("Cannot creat file")),
+        None => Box::new(io::stdout()),
+    };
+
+    let names: Vec<&str> = abc.priors.iter().map(|p| p.name.as_str()).collect();
+    writeln!(outfile, "{},distance,weight", names.join(",")).expect("Cannot write to file");
+
+    for particle in particles.iter() {
+        let values: Vec<String> = particle.values.iter().map(|v| v.to_string()).collect();
+        writeln!(
+            outfile,
+            "{},{},{}",
+            values.join(","),
+            particle.distance,
+            particle.weight
+        )
+        .expect("Cannot write to file");
+    }
+}
+
+//// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn particle(value: f64, distance: f64, weight: f64) -> Particle {
+        Particle {
+            values: vec![value],
+            distance,
+            weight,
+        }
+    }
+
+    #[test]
+    fn rejection_keeps_the_closest_particles() {
+        let particles = vec![
+            particle(1.0, 0.4, 1.0),
+            particle(2.0, f64::INFINITY, 1.0),
+            particle(3.0, 0.1, 1.0),
+            particle(4.0, 0.3, 1.0),
+            particle(5.0, 0.2, 1.0),
+        ];
+
+        let accepted = select_closest(particles, 3);
+        let values: Vec<f64> = accepted.iter().map(|p| p.values[0]).collect();
+        assert_eq!(values, vec![3.0, 5.0, 4.0]);
+        assert!(accepted
+            .iter()
+            .all(|p| (p.weight - 1.0 / 3.0).abs() < 1e-12));
+
+        // Collapsed simulations are left out even when too few remain
+        let particles = vec![particle(1.0, f64::INFINITY, 1.0), particle(2.0, 0.5, 1.0)];
+        let accepted = select_closest(particles, 2);
+        assert_eq!(accepted.len(), 1);
+        assert_eq!(accepted[0].values, vec![2.0]);
+    }
+
+    #[test]
+    fn smc_weights_are_normalised() {
+        // Importance weights keep their ratios and sum to 1
+        let mut particles = vec![
+            particle(1.0, 0.1, 2.0),
+            particle(2.0, 0.1, 6.0),
+            particle(3.0, 0.1, 0.5),
+            particle(4.0, 0.1, 1.5),
+        ];
+
+        normalize_weights(&mut particles);
+        let weights: Vec<f64> = particles.iter().map(|p| p.weight).collect();
+        let total: f64 = weights.iter().sum();
+
+        assert!((total - 1.0).abs() < 1e-12);
+        assert!((weights[0] - 0.2).abs() < 1e-12);
+        assert!((weights[1] - 0.6).abs() < 1e-12);
+        assert!((weights[2] - 0.05).abs() < 1e-12);
+        assert!((weights[3] - 0.15).abs() < 1e-12);
+    }
+
+    #[test]
+    fn collapsed_simulations_are_infinitely_distant() {
+        let abc = Abc {
+            base: Parameters::from_matches(&clap::App::new("test").get_matches_from(vec!["test"])),
+            priors: Vec::new(),
+            observed: Vec::new(),
+            statistics: vec![Statistic::Frequency],
+        };
+
+        let observed = Trajectory {
+            generations: vec![0, 4],
+            counts: vec![[25.0, 50.0, 25.0]; 2],
+            proportions: vec![[0.25, 0.5, 0.25]; 2],
+        };
+
+        // Both runs stop after generation 1 in the same state, the last
+        // state standing for later generations only without a collapse
+        let mut simulated = RunSummary {
+            adult_proportions: vec![[0.25, 0.5, 0.25], [0.25, 0.5, 0.25]],
+            collapsed: false,
+        };
+        assert_eq!(abc.trajectory_distance(&observed, &simulated), 0.0);
+
+        simulated.collapsed = true;
+        assert!(abc.trajectory_distance(&observed, &simulated).is_infinite());
+
+        // Observations before the collapse are compared as usual
+        let early = Trajectory {
+            generations: vec![1],
+            counts: vec![[25.0, 50.0, 25.0]],
+            proportions: vec![[0.25, 0.5, 0.25]],
+        };
+        assert_eq!(abc.trajectory_distance(&early, &simulated), 0.0);
+    }
+}
@@ -0,0 +1,179 @@
+//// Male body size
+// With the `trait` size model, each male gets a body size drawn from a
+// normal distribution around the mean of his genotype. Size then sets his
+// maturation time and mating success through a trade-off: maturation time
+// increases linearly with size and mating success is a power of size. The
+// defaults roughly reproduce the per-genotype constants, large AA males
+// maturing late and mating often and small BB males the opposite
+use rand::distributions::{Distribution, Normal};
+
+use std::process;
+use std::str::FromStr;
+
+use crate::parameters::Parameters;
+use crate::random::SimulationRng;
+use crate::{genotype_index, Fly, Genotype, Sex};
+
+//// Enums
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum SizeModel {
+    Genotype,
+    Trait,
+}
+
+impl FromStr for SizeModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "genotype" => Ok(SizeModel::Genotype),
+            "trait" => Ok(SizeModel::Trait),
+            _ => Err(format!("Unknown size model: {}", s)),
+        }
+    }
+}
+
+//// Structs
+pub struct BodySize {
+    model: SizeModel,
+    means: [f64; 3],
+    sd: f64,
+    maturation_intercept: f64,
+    maturation_slope: f64,
+    success_exponent: f64,
+}
+
+// Statistics undefined for too few males are left out
+pub struct SizeDistribution {
+    pub genotype: Genotype,
+    pub number: usize,
+    pub mean: Option<f64>,
+    pub sd: Option<f64>,
+    pub min: Option<f64>,
+    pub q25: Option<f64>,
+    pub median: Option<f64>,
+    pub q75: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl BodySize {
+    pub fn new(params: &Parameters) -> BodySize {
+        // Sizes are never negative, so a positive intercept and a slope of
+        // at least 0 keep every maturation time positive
+        if params.size_model == SizeModel::Trait
+            && (params.size_maturation_intercept <= 0.0 || params.size_maturation_slope < 0.0)
+        {
+            eprintln!(
+                "Size maturation needs a positive intercept and a non-negative slope: {}, {}",
+                params.size_maturation_intercept, params.size_maturation_slope
+            );
+            process::exit(1);
+        }
+
+        BodySize {
+            model: params.size_model,
+            means: [
+                params.male_size_aa,
+                params.male_size_ab,
+                params.male_size_bb,
+            ],
+            sd: params.male_size_sd,
+            maturation_intercept: params.size_maturation_intercept,
+            maturation_slope: params.size_maturation_slope,
+            success_exponent: params.size_success_exponent,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.model == SizeModel::Trait
+    }
+
+    pub fn sample(&self, sex: Sex, genotype: Genotype, rng: &mut SimulationRng) -> f64 {
+        // Body size of a new male, kept positive. Females are not given a
+        // size
+        if !self.is_active() || sex == Sex::Female {
+            return 0.0;
+        }
+
+        let mean = self.means[genotype_index(genotype)];
+
+        if self.sd > 0.0 {
+            Normal::new(mean, self.sd).sample(rng).max(0.0)
+        } else {
+            mean
+        }
+    }
+
+    pub fn maturation(&self, size: f64) -> f64 {
+        self.maturation_intercept + self.maturation_slope * size
+    }
+
+    pub fn success(&self, size: f64) -> f64 {
+        size.powf(self.success_exponent)
+    }
+
+    pub fn distribution(&self, flies: &[Fly]) -> Vec<SizeDistribution> {
+        // Summary of male sizes per genotype
+        [Genotype::AA, Genotype::AB, Genotype::BB]
+            .iter()
+            .map(|genotype| {
+                let mut sizes: Vec<f64> = flies
+                    .iter()
+                    .filter(|f| f.sex == Sex::Male && f.genotype == *genotype)
+                    .map(|f| f.size)
+                    .collect();
+                sizes.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+                let number = sizes.len();
+                let mean = if number > 0 {
+                    Some(sizes.iter().sum::<f64>() / number as f64)
+                } else {
+                    None
+                };
+                let sd = match mean {
+                    Some(mean) if number > 1 => {
+                        let variance = sizes.iter().map(|s| (s - mean).powi(2)).sum::<f64>()
+                            / (number as f64 - 1.0);
+                        Some(variance.sqrt())
+                    }
+                    _ => None,
+                };
+
+                SizeDistribution {
+                    genotype: *genotype,
+                    number,
+                    mean,
+                    sd,
+                    min: quantile(&sizes, 0.0),
+                    q25: quantile(&sizes, 0.25),
+                    median: quantile(&sizes, 0.5),
+                    q75: quantile(&sizes, 0.75),
+                    max: quantile(&sizes, 1.0),
+                }
+            })
+            .collect()
+    }
+}
+
+//// Functions
+pub fn optional_value(value: Option<f64>) -> String {
+    // CSV field of a statistic, empty when undefined
+    match value {
+        Some(value) => value.to_string(),
+        None => String::new(),
+    }
+}
+
+fn quantile(sorted: &[f64], q: f64) -> Option<f64> {
+    // Quantile of sorted values with linear interpolation
+    if sorted.is_empty() {
+        return None;
+    }
+
+    let position = q * (sorted.len() - 1) as f64;
+    let lower = position.floor() as usize;
+    let upper = position.ceil() as usize;
+    let fraction = position - lower as f64;
+
+    Some(sorted[lower] + fraction * (sorted[upper] - sorted[lower]))
+}
@@ -0,0 +1,351 @@
+//// Checkpoints
+// With `--checkpoint-interval`, the state of a run is saved every few
+// generations to a file ending in `.checkpoint`: the next generation, the
+// state of the random number generator, the eggs of each deme, the next
+// identifier, effective size trackers, the genealogy and VCF samples so far,
+// and the length of every output file. The temperature and environment
+// duration of each generation are drawn from the random number generator
+// and have no other state. With `--resume`, a run continues from its
+// checkpoint, output files being cut back to their length at that point, so
+// its output is the one the interrupted run would have written. Values are
+// saved as text, with floats as their bits to be restored exactly, and the
+// checkpoint is only used with the parameters it was written with. It is
+// removed once the run completes
+use std::fs::{self, File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+
+use crate::maternal::Mother;
+use crate::parameters::Parameters;
+use crate::random::SimulationRng;
+use crate::{Fly, Genotype, Sex};
+
+const FORMAT: &str = "coelopa_fastsim_checkpoint_1";
+
+// Offset basis and prime of the 64 bit FNV-1a hash
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+//// Structs
+pub struct Writer {
+    text: String,
+}
+
+impl Writer {
+    pub fn new(fingerprint: u64, generation: u32) -> Writer {
+        Writer {
+            text: format!("{}\n{} {}\n", FORMAT, fingerprint, generation),
+        }
+    }
+
+    pub fn value<T: ToString>(&mut self, value: T) {
+        self.text.push_str(&value.to_string());
+        self.text.push(' ');
+    }
+
+    pub fn float(&mut self, value: f64) {
+        self.value(value.to_bits());
+    }
+
+    pub fn none(&mut self) {
+        // Marker of a missing optional value
+        self.value("-");
+    }
+
+    pub fn end_line(&mut self) {
+        self.text.push('\n');
+    }
+
+    pub fn rng(&mut self, rng: &SimulationRng) {
+        for word in rng.state().iter() {
+            self.value(word);
+        }
+
+        self.end_line();
+    }
+
+    pub fn fly(&mut self, fly: &Fly) {
+        self.value(fly.sex);
+        self.value(fly.genotype);
+        self.value(fly.haplotypes[0]);
+        self.value(fly.haplotypes[1]);
+        self.value(fly.loci[0]);
+        self.value(fly.loci[1]);
+        self.float(fly.maturation);
+        self.float(fly.environment);
+
+        match fly.mother {
+            Some(mother) => {
+                self.value(mother.genotype);
+                self.float(mother.maturation);
+                self.float(mother.environment);
+            }
+            None => self.none(),
+        }
+
+        self.float(fly.size);
+        self.value(fly.id);
+        self.value(fly.parents[0]);
+        self.value(fly.parents[1]);
+
+        for origin in fly.origins.iter() {
+            self.value(origin.0);
+            self.value(origin.1);
+        }
+
+        self.end_line();
+    }
+
+    pub fn flies(&mut self, flies: &[Fly]) {
+        self.value(flies.len());
+        self.end_line();
+
+        for fly in flies.iter() {
+            self.fly(fly);
+        }
+    }
+
+    pub fn save(&self, path: &Path) {
+        // Replace the previous checkpoint only once the new one is complete
+        let temporary = path.with_extension("checkpoint.tmp");
+        let mut file = File::create(&temporary).expect("Cannot creat file");
+        file.write_all(self.text.as_bytes())
+            .expect("Cannot write to file");
+        file.sync_all().expect("Cannot write to file");
+        fs::rename(&temporary, path).expect("Cannot write to file");
+    }
+}
+
+pub struct Reader {
+    path: PathBuf,
+    tokens: std::vec::IntoIter<String>,
+    pub generation: u32,
+}
+
+impl Reader {
+    pub fn open(path: &Path, fingerprint: u64) -> Option<Reader> {
+        // Checkpoint of a run, none when the run never reached one
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(_) => return None,
+        };
+
+        let tokens: Vec<String> = text.split_whitespace().map(String::from).collect();
+        let mut reader = Reader {
+            path: path.to_path_buf(),
+            tokens: tokens.into_iter(),
+            generation: 0,
+        };
+
+        if reader.token() != FORMAT {
+            reader.fail("is not a checkpoint file");
+        }
+
+        if reader.value::<u64>() != fingerprint {
+            reader.fail("was written with different parameters");
+        }
+
+        reader.generation = reader.value();
+        Some(reader)
+    }
+
+    fn fail(&self, message: &str) -> ! {
+        eprintln!("Checkpoint {} {}", self.path.display(), message);
+        process::exit(1);
+    }
+
+    fn token(&mut self) -> String {
+        match self.tokens.next() {
+            Some(token) => token,
+            None => self.fail("is truncated"),
+        }
+    }
+
+    pub fn value<T: FromStr>(&mut self) -> T {
+        let token = self.token();
+
+        match token.parse::<T>() {
+            Ok(value) => value,
+            Err(_) => self.fail(&format!("has an invalid value: {}", token)),
+        }
+    }
+
+    pub fn float(&mut self) -> f64 {
+        f64::from_bits(self.value())
+    }
+
+    pub fn none(&mut self) -> bool {
+        // Whether a missing optional value comes next, skipping its marker
+        let none = self.tokens.as_slice().first().map(String::as_str) == Some("-");
+
+        if none {
+            self.tokens.next();
+        }
+
+        none
+    }
+
+    pub fn rng(&mut self) -> SimulationRng {
+        SimulationRng::from_state([self.value(), self.value(), self.value(), self.value()])
+    }
+
+    fn sex(&mut self) -> Sex {
+        match self.token().as_str() {
+            "female" => Sex::Female,
+            "male" => Sex::Male,
+            token => self.fail(&format!("has an invalid sex: {}", token)),
+        }
+    }
+
+    fn genotype(&mut self) -> Genotype {
+        match self.token().as_str() {
+            "AA" => Genotype::AA,
+            "AB" => Genotype::AB,
+            "BB" => Genotype::BB,
+            token => self.fail(&format!("has an invalid genotype: {}", token)),
+        }
+    }
+
+    pub fn fly(&mut self) -> Fly {
+        let sex = self.sex();
+        let genotype = self.genotype();
+        let haplotypes = [self.value(), self.value()];
+        let loci = [self.value(), self.value()];
+        let maturation = self.float();
+        let environment = self.float();
+
+        let mother = if self.none() {
+            None
+        } else {
+            Some(Mother {
+                genotype: self.genotype(),
+                maturation: self.float(),
+                environment: self.float(),
+            })
+        };
+
+        Fly {
+            sex,
+            genotype,
+            haplotypes,
+            loci,
+            maturation,
+            environment,
+            mother,
+            size: self.float(),
+            id: self.value(),
+            parents: [self.value(), self.value()],
+            origins: [(self.value(), self.value()), (self.value(), self.value())],
+        }
+    }
+
+    pub fn flies(&mut self) -> Vec<Fly> {
+        let number: usize = self.value();
+        (0..number).map(|_| self.fly()).collect()
+    }
+}
+
+pub struct OutputFiles {
+    // Output files in the order they are opened, with their length in the
+    // checkpoint when resuming
+    paths: Vec<String>,
+    lengths: Option<Vec<u64>>,
+}
+
+impl OutputFiles {
+    pub fn new(checkpoint: Option<&mut Reader>) -> OutputFiles {
+        let lengths = checkpoint.map(|checkpoint| {
+            let number: usize = checkpoint.value();
+            (0..number).map(|_| checkpoint.value()).collect()
+        });
+
+        OutputFiles {
+            paths: Vec::new(),
+            lengths,
+        }
+    }
+
+    pub fn open(&mut self, path: String, header: &[u8]) -> File {
+        // Create an output file and write its header, or reopen it at its
+        // length in the checkpoint
+        let file = match &self.lengths {
+            None => {
+                let mut file = File::create(&path).expect("Cannot creat file");
+                file.write_all(header).expect("Cannot write to file");
+                file
+            }
+            Some(lengths) => {
+                let length = lengths.get(self.paths.len()).cloned();
+                let file = OpenOptions::new().write(true).open(&path).ok();
+
+                match (file, length) {
+                    (Some(mut file), Some(length))
+                        if file.metadata().map(|m| m.len()).unwrap_or(0) >= length =>
+                    {
+                        file.set_len(length).expect("Cannot write to file");
+                        file.seek(SeekFrom::End(0)).expect("Cannot write to file");
+                        file
+                    }
+                    _ => {
+                        eprintln!("Cannot resume output file {}", path);
+                        process::exit(1);
+                    }
+                }
+            }
+        };
+
+        self.paths.push(path);
+        file
+    }
+
+    pub fn save(&self, checkpoint: &mut Writer) {
+        checkpoint.value(self.paths.len());
+
+        for path in self.paths.iter() {
+            let length = fs::metadata(path).expect("Cannot read file").len();
+            checkpoint.value(length);
+        }
+
+        checkpoint.end_line();
+    }
+}
+
+//// Functions
+pub fn fingerprint(params: &Parameters, demes_parameters: &[Parameters]) -> u64 {
+    // FNV-1a hash of the parameters of a run, one line each, leaving out
+    // those only deciding how it is checkpointed and reported on screen. The
+    // hash is fixed, unlike that of the standard library, so checkpoints
+    // remain usable by other builds
+    let normalise = |params: &Parameters| {
+        let mut params = params.clone();
+        params.checkpoint_interval = 0;
+        params.resume = false;
+        params.quiet = false;
+        format!("{:?}\n", params)
+    };
+
+    let mut hash = FNV_OFFSET;
+
+    for p in std::iter::once(params).chain(demes_parameters.iter()) {
+        for byte in normalise(p).bytes() {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    hash
+}
+
+pub fn checkpoint_path(output_file: &str) -> PathBuf {
+    Path::new(output_file).with_extension("checkpoint")
+}
+
+pub fn remove(path: &Path) {
+    // Remove the checkpoint of a completed run, which a later `--resume`
+    // must not continue from
+    if path.exists() {
+        fs::remove_file(path).expect("Cannot remove checkpoint");
+    }
+}
@@ -0,0 +1,104 @@
+//// Carrying capacity through time
+// The carrying capacity replaces `number_eggs_per_generation` as the number
+// of eggs kept by truncation, or as the density scale when egg survival is
+// density dependent
+use std::f64::consts::PI;
+use std::fs;
+use std::process;
+use std::str::FromStr;
+
+//// Enums
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum CapacityModel {
+    Constant,
+    Periodic,
+    Schedule,
+}
+
+impl FromStr for CapacityModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "constant" => Ok(CapacityModel::Constant),
+            "periodic" => Ok(CapacityModel::Periodic),
+            "schedule" => Ok(CapacityModel::Schedule),
+            _ => Err(format!("Unknown carrying capacity model: {}", s)),
+        }
+    }
+}
+
+//// Structs
+pub struct CarryingCapacity {
+    pub model: CapacityModel,
+    pub base: f64,
+    pub amplitude: f64,
+    pub period: f64,
+    pub schedule: Vec<(u32, f64)>,
+}
+
+impl CarryingCapacity {
+    pub fn at(&self, generation: u32) -> f64 {
+        match self.model {
+            CapacityModel::Constant => self.base,
+
+            // Sine wave around the base value, amplitude relative to base
+            CapacityModel::Periodic => {
+                let phase = 2.0 * PI * f64::from(generation) / self.period;
+                (self.base * (1.0 + self.amplitude * phase.sin())).max(0.0)
+            }
+
+            CapacityModel::Schedule => scheduled_value(&self.schedule, generation, self.base),
+        }
+    }
+}
+
+//// Functions
+pub fn read_schedule(path: &str, name: &str) -> Vec<(u32, f64)> {
+    // Read `generation,value` rows, ignoring comments. `name` describes the
+    // scheduled value in error messages
+    let content = fs::read_to_string(path).unwrap_or_else(|_| {
+        eprintln!("Cannot read {} schedule: {}", name, path);
+        process::exit(1);
+    });
+
+    let mut schedule = Vec::new();
+
+    for line in content.lines() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let values: Vec<&str> = line.split(',').map(|v| v.trim()).collect();
+
+        if values.len() != 2 {
+            eprintln!("Rows of the {} schedule need 2 values: {}", name, line);
+            process::exit(1);
+        }
+
+        let generation = values[0]
+            .parse::<u32>()
+            .expect("Cannot parse schedule generation");
+        let value = values[1]
+            .parse::<f64>()
+            .expect("Cannot parse schedule value");
+
+        schedule.push((generation, value));
+    }
+
+    schedule.sort_by_key(|(g, _)| *g);
+    schedule
+}
+
+pub fn scheduled_value(schedule: &[(u32, f64)], generation: u32, default: f64) -> f64 {
+    // Value of the last scheduled generation reached so far, `default`
+    // before the first one
+    schedule
+        .iter()
+        .take_while(|(g, _)| *g <= generation)
+        .last()
+        .map(|(_, value)| *value)
+        .unwrap_or(default)
+}
@@ -0,0 +1,355 @@
+//// Effective population size
+// Adults receive identifiers and eggs keep those of their parents, so the
+// number of offspring of each adult reaching adulthood in the next
+// generation is known, adults that never mature having none. The variance
+// effective size of `N` parents with offspring numbers of mean `k` and
+// variance `Vk`, each offspring counting for both of its parents, is
+// `(N k - 2) / (k - 1 + Vk / k)` (Crow and Kimura 1970). Under X linkage,
+// sons only count for their mother, their father passing them no inversion
+// chromosome. The temporal
+// effective size between the first and a later generation of mature adults
+// uses the standardized variance in allele frequencies `Fc` of Nei and
+// Tajima (1981), averaged over the inversion and neutral sites and
+// corrected for the number of mature adults compared
+use std::collections::HashMap;
+
+use crate::checkpoint;
+use crate::neutral::Linkage;
+use crate::{Fly, Genotype};
+
+//// Structs
+pub struct VarianceNe {
+    pub parents: usize,
+    pub mean_offspring: f64,
+    pub variance_offspring: f64,
+    pub ne: f64,
+}
+
+#[derive(Default)]
+pub struct DriftTracker {
+    // Identifiers of the adults of the previous generation
+    parents: Vec<u64>,
+    // Allele frequencies and number of mature adults of the first generation
+    initial: Option<(Vec<f64>, usize)>,
+    pub variance_ne: Vec<f64>,
+    pub temporal_ne: f64,
+}
+
+impl DriftTracker {
+    pub fn variance(&mut self, offspring: &HashMap<u64, u32>) -> Option<VarianceNe> {
+        // Variance effective size of the previous generation, none in the
+        // first generation
+        if self.parents.is_empty() {
+            return None;
+        }
+
+        let numbers: Vec<f64> = self
+            .parents
+            .iter()
+            .map(|id| f64::from(*offspring.get(id).unwrap_or(&0)))
+            .collect();
+        let number_parents = numbers.len() as f64;
+        let mean = numbers.iter().sum::<f64>() / number_parents;
+        let variance = numbers.iter().map(|k| (k - mean).powi(2)).sum::<f64>() / number_parents;
+
+        let ne = if numbers.len() > 1 && mean > 0.0 {
+            (number_parents * mean - 2.0) / (mean - 1.0 + variance / mean)
+        } else {
+            f64::NAN
+        };
+
+        self.variance_ne.push(ne);
+
+        Some(VarianceNe {
+            parents: numbers.len(),
+            mean_offspring: mean,
+            variance_offspring: variance,
+            ne,
+        })
+    }
+
+    pub fn set_parents(&mut self, adults: &[Fly]) {
+        self.parents = adults.iter().map(|a| a.id).collect();
+    }
+
+    pub fn temporal(&mut self, mature_adults: &[Fly], linkage: &Linkage, generation: u32) -> f64 {
+        // Temporal effective size since the first generation, which is
+        // remembered on the first call
+        let current = frequencies(mature_adults, linkage);
+        let size = mature_adults.len();
+
+        let (initial, initial_size) = match &self.initial {
+            Some(initial) => initial,
+            None => {
+                self.initial = Some((current, size));
+                self.temporal_ne = f64::NAN;
+                return f64::NAN;
+            }
+        };
+
+        let values: Vec<f64> = initial
+            .iter()
+            .zip(current.iter())
+            .filter_map(|(x, y)| standardized_variance(*x, *y))
+            .collect();
+
+        self.temporal_ne = if values.is_empty() || *initial_size == 0 || size == 0 {
+            f64::NAN
+        } else {
+            let fc = values.iter().sum::<f64>() / values.len() as f64;
+            let drift = fc - 1.0 / (2.0 * *initial_size as f64) - 1.0 / (2.0 * size as f64);
+
+            if drift > 0.0 {
+                f64::from(generation) / (2.0 * drift)
+            } else {
+                f64::INFINITY
+            }
+        };
+
+        self.temporal_ne
+    }
+
+    pub fn save(&self, checkpoint: &mut checkpoint::Writer) {
+        checkpoint.value(self.parents.len());
+
+        for id in self.parents.iter() {
+            checkpoint.value(id);
+        }
+
+        match &self.initial {
+            Some((frequencies, size)) => {
+                checkpoint.value(frequencies.len());
+
+                for f in frequencies.iter() {
+                    checkpoint.float(*f);
+                }
+
+                checkpoint.value(size);
+            }
+            None => checkpoint.none(),
+        }
+
+        checkpoint.value(self.variance_ne.len());
+
+        for ne in self.variance_ne.iter() {
+            checkpoint.float(*ne);
+        }
+
+        checkpoint.float(self.temporal_ne);
+        checkpoint.end_line();
+    }
+
+    pub fn restore(&mut self, checkpoint: &mut checkpoint::Reader) {
+        let number: usize = checkpoint.value();
+        self.parents = (0..number).map(|_| checkpoint.value()).collect();
+
+        self.initial = if checkpoint.none() {
+            None
+        } else {
+            let number: usize = checkpoint.value();
+            let frequencies = (0..number).map(|_| checkpoint.float()).collect();
+            Some((frequencies, checkpoint.value()))
+        };
+
+        let number: usize = checkpoint.value();
+        self.variance_ne = (0..number).map(|_| checkpoint.float()).collect();
+        self.temporal_ne = checkpoint.float();
+    }
+
+    pub fn harmonic_mean_variance_ne(&self) -> f64 {
+        // Over generations with a positive and finite estimate
+        let values: Vec<f64> = self
+            .variance_ne
+            .iter()
+            .filter(|ne| ne.is_finite() && **ne > 0.0)
+            .cloned()
+            .collect();
+
+        if values.is_empty() {
+            return f64::NAN;
+        }
+
+        values.len() as f64 / values.iter().map(|ne| 1.0 / ne).sum::<f64>()
+    }
+}
+
+//// Functions
+pub fn offspring_numbers<'a>(
+    adults: impl Iterator<Item = &'a Fly>,
+    linkage: &Linkage,
+) -> HashMap<u64, u32> {
+    // Number of adults descending from each parent identifier, hemizygous
+    // sons only from their mother, the first parent
+    let mut numbers = HashMap::new();
+
+    for adult in adults {
+        let number_parents = if linkage.hemizygous(adult) { 1 } else { 2 };
+
+        for parent in adult
+            .parents
+            .iter()
+            .take(number_parents)
+            .filter(|p| **p != 0)
+        {
+            *numbers.entry(*parent).or_insert(0) += 1;
+        }
+    }
+
+    numbers
+}
+
+fn frequencies(flies: &[Fly], linkage: &Linkage) -> Vec<f64> {
+    // Frequency of the A arrangement followed by those of neutral sites
+    let mut chromosomes_a = 0.0;
+    let mut chromosomes = 0.0;
+
+    for fly in flies.iter() {
+        let number_chromosomes = if linkage.hemizygous(fly) { 1.0 } else { 2.0 };
+
+        chromosomes_a += match fly.genotype {
+            Genotype::AA => number_chromosomes,
+            Genotype::AB => 1.0,
+            Genotype::BB => 0.0,
+        };
+        chromosomes += number_chromosomes;
+    }
+
+    let mut values = vec![chromosomes_a / chromosomes];
+
+    if linkage.is_active() {
+        values.extend(linkage.site_frequencies(flies));
+    }
+
+    values
+}
+
+fn standardized_variance(x: f64, y: f64) -> Option<f64> {
+    // Fc of a biallelic locus, averaged over both alleles, or none when it
+    // is fixed for the same allele in both generations
+    let allele = |x: f64, y: f64| {
+        let denominator = (x + y) / 2.0 - x * y;
+
+        if denominator > 0.0 {
+            Some((x - y).powi(2) / denominator)
+        } else {
+            None
+        }
+    };
+
+    if x.is_nan() || y.is_nan() {
+        return None;
+    }
+
+    match (allele(x, y), allele(1.0 - x, 1.0 - y)) {
+        (Some(a), Some(b)) => Some((a + b) / 2.0),
+        _ => None,
+    }
+}
+
+//// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parameters::Parameters;
+    use crate::{Inheritance, Sex};
+
+    fn fly(sex: Sex, genotype: Genotype, id: u64, parents: [u64; 2]) -> Fly {
+        Fly {
+            sex,
+            genotype,
+            haplotypes: [0, 0],
+            loci: [0, 0],
+            maturation: 0.0,
+            environment: 0.0,
+            mother: None,
+            size: 0.0,
+            id,
+            parents,
+            origins: [(0, 0), (0, 0)],
+        }
+    }
+
+    fn linkage(inheritance: Inheritance) -> Linkage {
+        let mut params =
+            Parameters::from_matches(&clap::App::new("test").get_matches_from(vec!["test"]));
+        params.inheritance = inheritance;
+        Linkage::new(&params)
+    }
+
+    fn parents() -> Vec<Fly> {
+        vec![
+            fly(Sex::Female, Genotype::AB, 1, [0, 0]),
+            fly(Sex::Female, Genotype::AB, 2, [0, 0]),
+            fly(Sex::Male, Genotype::AB, 3, [0, 0]),
+            fly(Sex::Male, Genotype::AB, 4, [0, 0]),
+            fly(Sex::Male, Genotype::AB, 5, [0, 0]),
+        ]
+    }
+
+    fn offspring() -> Vec<Fly> {
+        vec![
+            fly(Sex::Male, Genotype::AB, 6, [1, 3]),
+            fly(Sex::Female, Genotype::AB, 7, [1, 3]),
+            fly(Sex::Male, Genotype::AB, 8, [1, 4]),
+            fly(Sex::Female, Genotype::AB, 9, [2, 4]),
+        ]
+    }
+
+    #[test]
+    fn variance_ne_of_known_offspring_numbers() {
+        // Offspring numbers 3, 1, 2, 2 and 0, of mean 1.6 and variance
+        // 1.04, give Ne = (5 * 1.6 - 2) / (0.6 + 1.04 / 1.6) = 4.8
+        let mut tracker = DriftTracker::default();
+        assert!(tracker.variance(&HashMap::new()).is_none());
+
+        tracker.set_parents(&parents());
+        let offspring = offspring_numbers(offspring().iter(), &linkage(Inheritance::Autosomal));
+        let variance_ne = tracker.variance(&offspring).unwrap();
+
+        assert_eq!(variance_ne.parents, 5);
+        assert!((variance_ne.mean_offspring - 1.6).abs() < 1e-12);
+        assert!((variance_ne.variance_offspring - 1.04).abs() < 1e-12);
+        assert!((variance_ne.ne - 4.8).abs() < 1e-12);
+    }
+
+    #[test]
+    fn sons_count_for_their_mother_under_x_linkage() {
+        let autosomal = offspring_numbers(offspring().iter(), &linkage(Inheritance::Autosomal));
+        assert_eq!(autosomal[&1], 3);
+        assert_eq!(autosomal[&3], 2);
+        assert_eq!(autosomal[&4], 2);
+
+        // Fathers keep only their daughters
+        let x_linked = offspring_numbers(offspring().iter(), &linkage(Inheritance::XLinked));
+        assert_eq!(x_linked[&1], 3);
+        assert_eq!(x_linked[&2], 1);
+        assert_eq!(x_linked[&3], 1);
+        assert_eq!(x_linked[&4], 1);
+        assert!(!x_linked.contains_key(&5));
+    }
+
+    #[test]
+    fn temporal_ne_of_known_frequency_change() {
+        // From a frequency of A of 0.5 to 0.6 in 10 generations with 100
+        // adults each time, Fc = 0.04 and Ne = 10 / (2 (0.04 - 0.01))
+        let linkage = linkage(Inheritance::Autosomal);
+        let initial: Vec<Fly> = (0..100)
+            .map(|i| fly(Sex::Female, Genotype::AB, i, [0, 0]))
+            .collect();
+        let later: Vec<Fly> = (0..100)
+            .map(|i| {
+                let genotype = if i < 20 { Genotype::AA } else { Genotype::AB };
+                fly(Sex::Female, genotype, i, [0, 0])
+            })
+            .collect();
+
+        let mut tracker = DriftTracker::default();
+        assert!(tracker.temporal(&initial, &linkage, 0).is_nan());
+
+        let ne = tracker.temporal(&later, &linkage, 10);
+        assert!((ne - 10.0 / 0.06).abs() < 1e-9, "{}", ne);
+
+        // No change beyond sampling gives an infinite estimate
+        assert!(tracker.temporal(&initial, &linkage, 20).is_infinite());
+    }
+}
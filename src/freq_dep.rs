@@ -0,0 +1,189 @@
+//// Frequency dependent selection on male mating success
+// Each model returns one multiplier per male genotype, ordered as AA, AB,
+// BB, from the proportions of mature males of each genotype. Multipliers
+// are applied on top of the `male_success` values before mates are drawn
+use std::fs;
+use std::process;
+use std::str::FromStr;
+
+//// Enums
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum FreqDepModel {
+    Linear,
+    RareMale,
+    Negative,
+    Table,
+}
+
+impl FromStr for FreqDepModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(FreqDepModel::Linear),
+            "rare-male" => Ok(FreqDepModel::RareMale),
+            "negative" => Ok(FreqDepModel::Negative),
+            "table" => Ok(FreqDepModel::Table),
+            _ => Err(format!("Unknown frequency dependence model: {}", s)),
+        }
+    }
+}
+
+//// Traits
+pub trait FrequencyDependence {
+    fn coefficients(&self, proportions: &[f64; 3]) -> [f64; 3];
+}
+
+//// Structs
+// Original model: AB and BB males lose success as AA males become rare
+pub struct Linear {
+    pub coef: f64,
+}
+
+impl FrequencyDependence for Linear {
+    fn coefficients(&self, proportions: &[f64; 3]) -> [f64; 3] {
+        let proportion_aa = proportions[0];
+
+        [
+            1.0,
+            1.0 - self.coef * (1.0 - proportion_aa) / 2.0,
+            1.0 - self.coef * (1.0 - proportion_aa),
+        ]
+    }
+}
+
+// Rare males of any genotype get an advantage growing with their rarity
+pub struct RareMale {
+    pub coef: f64,
+    pub exponent: f64,
+}
+
+impl FrequencyDependence for RareMale {
+    fn coefficients(&self, proportions: &[f64; 3]) -> [f64; 3] {
+        let mut coefficients = [0.0; 3];
+
+        for (c, p) in coefficients.iter_mut().zip(proportions.iter()) {
+            *c = 1.0 + self.coef * (1.0 - p).powf(self.exponent);
+        }
+
+        coefficients
+    }
+}
+
+// Success of each genotype decreases linearly with its own frequency
+pub struct NegativeOwn {
+    pub coef: f64,
+}
+
+impl FrequencyDependence for NegativeOwn {
+    fn coefficients(&self, proportions: &[f64; 3]) -> [f64; 3] {
+        let mut coefficients = [0.0; 3];
+
+        for (c, p) in coefficients.iter_mut().zip(proportions.iter()) {
+            *c = (1.0 - self.coef * p).max(0.0);
+        }
+
+        coefficients
+    }
+}
+
+// User supplied multipliers, linearly interpolated at the frequency of
+// each genotype. Rows are `frequency,AA,AB,BB` sorted by frequency
+pub struct Table {
+    pub rows: Vec<[f64; 4]>,
+}
+
+impl Table {
+    pub fn from_file(path: &str) -> Table {
+        let content = fs::read_to_string(path).unwrap_or_else(|_| {
+            eprintln!("Cannot read frequency dependence table: {}", path);
+            process::exit(1);
+        });
+
+        let mut rows = Vec::new();
+
+        for line in content.lines() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let values: Vec<f64> = line
+                .split(',')
+                .map(|v| v.trim().parse::<f64>().expect("Cannot parse table value"))
+                .collect();
+
+            if values.len() != 4 {
+                eprintln!("Frequency dependence table rows need 4 values: {}", line);
+                process::exit(1);
+            }
+
+            rows.push([values[0], values[1], values[2], values[3]]);
+        }
+
+        if rows.is_empty() {
+            eprintln!("Frequency dependence table is empty: {}", path);
+            process::exit(1);
+        }
+
+        rows.sort_by(|a, b| a[0].partial_cmp(&b[0]).unwrap());
+
+        Table { rows }
+    }
+
+    fn interpolate(&self, column: usize, frequency: f64) -> f64 {
+        let first = self.rows[0];
+        let last = self.rows[self.rows.len() - 1];
+
+        if frequency.is_nan() {
+            return frequency;
+        } else if frequency <= first[0] {
+            return first[column];
+        } else if frequency >= last[0] {
+            return last[column];
+        }
+
+        for pair in self.rows.windows(2) {
+            let (low, high) = (pair[0], pair[1]);
+
+            if frequency <= high[0] {
+                let position = (frequency - low[0]) / (high[0] - low[0]);
+                return low[column] + position * (high[column] - low[column]);
+            }
+        }
+
+        last[column]
+    }
+}
+
+impl FrequencyDependence for Table {
+    fn coefficients(&self, proportions: &[f64; 3]) -> [f64; 3] {
+        [
+            self.interpolate(1, proportions[0]).max(0.0),
+            self.interpolate(2, proportions[1]).max(0.0),
+            self.interpolate(3, proportions[2]).max(0.0),
+        ]
+    }
+}
+
+//// Functions
+pub fn from_model(
+    model: FreqDepModel,
+    coef: f64,
+    exponent: f64,
+    table: Option<&str>,
+) -> Box<dyn FrequencyDependence> {
+    match model {
+        FreqDepModel::Linear => Box::new(Linear { coef }),
+        FreqDepModel::RareMale => Box::new(RareMale { coef, exponent }),
+        FreqDepModel::Negative => Box::new(NegativeOwn { coef }),
+        FreqDepModel::Table => match table {
+            Some(path) => Box::new(Table::from_file(path)),
+            None => {
+                eprintln!("Frequency dependence model 'table' needs --male-freq-dep-table");
+                process::exit(1);
+            }
+        },
+    }
+}
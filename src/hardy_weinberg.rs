@@ -0,0 +1,197 @@
+//// Hardy-Weinberg diagnostics
+// Observed and expected heterozygosity of the inversion, the inbreeding
+// coefficient F_IS = 1 - Ho / He, positive for a heterozygote deficit and
+// negative for an excess such as produced by overdominance, and the p-value
+// of the exact test of Hardy-Weinberg proportions of Wigginton et al.
+// (2005) from genotype counts. Under X linkage, only females are diploid
+// and males are left out
+use crate::{Fly, Genotype, Inheritance, Sex};
+
+//// Structs
+#[derive(Copy, Clone, Debug)]
+pub struct HardyWeinberg {
+    pub observed_heterozygosity: f64,
+    pub expected_heterozygosity: f64,
+    pub fis: f64,
+    pub p_value: f64,
+}
+
+impl HardyWeinberg {
+    pub fn of(flies: &[Fly], inheritance: Inheritance) -> HardyWeinberg {
+        let mut counts = [0; 3];
+
+        for fly in flies.iter() {
+            if inheritance == Inheritance::XLinked && fly.sex == Sex::Male {
+                continue;
+            }
+
+            match fly.genotype {
+                Genotype::AA => counts[0] += 1,
+                Genotype::AB => counts[1] += 1,
+                Genotype::BB => counts[2] += 1,
+            }
+        }
+
+        HardyWeinberg::from_counts(counts[0], counts[1], counts[2])
+    }
+
+    pub fn from_counts(aa: u64, ab: u64, bb: u64) -> HardyWeinberg {
+        let number = (aa + ab + bb) as f64;
+        let frequency_a = (2 * aa + ab) as f64 / (2.0 * number);
+        let observed_heterozygosity = ab as f64 / number;
+        let expected_heterozygosity = 2.0 * frequency_a * (1.0 - frequency_a);
+
+        let fis = if expected_heterozygosity > 0.0 {
+            1.0 - observed_heterozygosity / expected_heterozygosity
+        } else {
+            f64::NAN
+        };
+
+        HardyWeinberg {
+            observed_heterozygosity,
+            expected_heterozygosity,
+            fis,
+            p_value: exact_test(aa, ab, bb),
+        }
+    }
+}
+
+//// Functions
+fn exact_test(aa: u64, ab: u64, bb: u64) -> f64 {
+    // Sum of the probabilities, given the allele counts, of numbers of
+    // heterozygotes no more likely than the observed one
+    let number = aa + ab + bb;
+
+    if number == 0 {
+        return f64::NAN;
+    }
+
+    let rare_homozygotes = aa.min(bb);
+    let rare = (2 * rare_homozygotes + ab) as usize;
+    let mut probabilities = vec![0.0; rare + 1];
+
+    // Start from the most likely number of heterozygotes, with the parity
+    // of the number of rare alleles
+    let mut middle = rare * (2 * number as usize - rare) / (2 * number as usize);
+
+    if (rare & 1) != (middle & 1) {
+        middle += 1;
+    }
+
+    probabilities[middle] = 1.0;
+    let mut total = 1.0;
+
+    // Fewer heterozygotes
+    let mut heterozygotes = middle;
+    let mut homozygotes_rare = ((rare - middle) / 2) as f64;
+    let mut homozygotes_common = number as f64 - middle as f64 - homozygotes_rare;
+
+    while heterozygotes > 1 {
+        let h = heterozygotes as f64;
+        probabilities[heterozygotes - 2] = probabilities[heterozygotes] * h * (h - 1.0)
+            / (4.0 * (homozygotes_rare + 1.0) * (homozygotes_common + 1.0));
+        total += probabilities[heterozygotes - 2];
+        homozygotes_rare += 1.0;
+        homozygotes_common += 1.0;
+        heterozygotes -= 2;
+    }
+
+    // More heterozygotes
+    let mut heterozygotes = middle;
+    let mut homozygotes_rare = ((rare - middle) / 2) as f64;
+    let mut homozygotes_common = number as f64 - middle as f64 - homozygotes_rare;
+
+    while heterozygotes + 2 <= rare {
+        let h = heterozygotes as f64;
+        probabilities[heterozygotes + 2] =
+            probabilities[heterozygotes] * 4.0 * homozygotes_rare * homozygotes_common
+                / ((h + 2.0) * (h + 1.0));
+        total += probabilities[heterozygotes + 2];
+        homozygotes_rare -= 1.0;
+        homozygotes_common -= 1.0;
+        heterozygotes += 2;
+    }
+
+    let observed = probabilities[ab as usize];
+    let p_value: f64 = probabilities
+        .iter()
+        .filter(|p| **p <= observed)
+        .sum::<f64>()
+        / total;
+
+    p_value.min(1.0)
+}
+
+//// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(value: f64, expected: f64) {
+        assert!(
+            (value - expected).abs() <= 1e-9 * expected.abs(),
+            "{} is not {}",
+            value,
+            expected
+        );
+    }
+
+    #[test]
+    fn exact_test_matches_enumeration() {
+        // P-values summing the exact probabilities of Wigginton et al.
+        // (2005), 2^ab N! nA! nB! / (aa! ab! bb! (2N)!), over all numbers of
+        // heterozygotes, with rational arithmetic
+        assert_close(exact_test(1, 19, 80), 1.0);
+        assert_close(exact_test(2, 17, 81), 0.284_041_500_443_367_6);
+        assert_close(exact_test(0, 21, 79), 0.593_645_175_927_316_7);
+        assert_close(exact_test(5, 11, 84), 9.188_594_677_093_134e-4);
+        assert_close(exact_test(10, 10, 10), 0.074_219_431_380_535_23);
+        assert_close(exact_test(14, 57, 29), 0.150_680_076_515_761_43);
+        assert_close(exact_test(57, 14, 29), 1.504_571_248_956_001_2e-12);
+        assert_close(exact_test(30, 20, 50), 4.257_207_014_425_199e-9);
+        assert_close(exact_test(25, 50, 25), 1.0);
+        assert_close(exact_test(1, 0, 1), 1.0 / 3.0);
+    }
+
+    #[test]
+    fn exact_test_edge_counts() {
+        // All heterozygotes and only homozygotes of both kinds are extreme
+        assert_close(exact_test(0, 100, 0), 1.511_390_827_305_58e-29);
+        assert_close(exact_test(50, 0, 50), 1.114_224_180_581_451e-30);
+
+        // Monomorphic samples and single heterozygotes are the only
+        // possible outcome
+        assert_close(exact_test(100, 0, 0), 1.0);
+        assert_close(exact_test(0, 0, 100), 1.0);
+        assert_close(exact_test(0, 1, 0), 1.0);
+        assert!(exact_test(0, 0, 0).is_nan());
+    }
+
+    #[test]
+    fn heterozygosity_and_fis() {
+        // Hardy-Weinberg proportions
+        let hw = HardyWeinberg::from_counts(25, 50, 25);
+        assert_close(hw.observed_heterozygosity, 0.5);
+        assert_close(hw.expected_heterozygosity, 0.5);
+        assert!(hw.fis.abs() < 1e-12);
+
+        // Heterozygote deficit, p = 0.45
+        let hw = HardyWeinberg::from_counts(30, 30, 40);
+        assert_close(hw.observed_heterozygosity, 0.3);
+        assert_close(hw.expected_heterozygosity, 0.495);
+        assert_close(hw.fis, 1.0 - 0.3 / 0.495);
+
+        // Heterozygote excess, as under overdominance
+        let hw = HardyWeinberg::from_counts(0, 100, 0);
+        assert_close(hw.observed_heterozygosity, 1.0);
+        assert_close(hw.expected_heterozygosity, 0.5);
+        assert_close(hw.fis, -1.0);
+
+        // No expected heterozygosity in a monomorphic sample
+        let hw = HardyWeinberg::from_counts(100, 0, 0);
+        assert_close(hw.observed_heterozygosity, 0.0);
+        assert_close(hw.expected_heterozygosity, 0.0);
+        assert!(hw.fis.is_nan());
+        assert_close(hw.p_value, 1.0);
+    }
+}
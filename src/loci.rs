@@ -0,0 +1,422 @@
+//// Additional biallelic loci
+// Besides the focal inversion, each chromosome can carry up to 32 other
+// biallelic loci, such as other inversions, stored as the bits of an
+// integer (bit set for allele B). Loci are listed in order along the
+// genome and each has a recombination fraction with the previous locus,
+// the first one with the focal inversion. A fraction of 0.5 means an
+// unlinked locus. Each locus has relative values for egg survival, female
+// eggs, male success and maturation time per genotype, combined with the
+// values of the focal inversion additively or multiplicatively, plus
+// optional pairwise epistasis between genotypes of two loci. Maturation
+// values combine into a factor of the maturation time of each sex and
+// genotype at the focal inversion, above 1 for a slower development
+use rand::Rng;
+
+use std::fs;
+use std::process;
+use std::str::FromStr;
+
+use crate::neutral::chromosome_arrangements;
+use crate::random::SimulationRng;
+use crate::{genotype_index, Fly, Genotype, Inheritance, Sex};
+
+pub const MAX_LOCI: usize = 32;
+
+// Name of the focal inversion in the epistasis file
+pub const FOCAL_LOCUS: &str = "inversion";
+
+//// Enums
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum LocusCombination {
+    Additive,
+    Multiplicative,
+}
+
+impl FromStr for LocusCombination {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "additive" => Ok(LocusCombination::Additive),
+            "multiplicative" => Ok(LocusCombination::Multiplicative),
+            _ => Err(format!("Unknown locus combination: {}", s)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Component {
+    EggSurvival,
+    FemaleEggs,
+    MaleSuccess,
+    Maturation,
+}
+
+impl FromStr for Component {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "egg_survival" => Ok(Component::EggSurvival),
+            "female_eggs" => Ok(Component::FemaleEggs),
+            "male_success" => Ok(Component::MaleSuccess),
+            "maturation" => Ok(Component::Maturation),
+            _ => Err(format!("Unknown fitness component: {}", s)),
+        }
+    }
+}
+
+//// Structs
+#[derive(Clone, Debug)]
+pub struct Locus {
+    pub name: String,
+    pub recombination: f64,
+    pub frequency_b: f64,
+    pub egg_survival: [f64; 3],
+    pub female_eggs: [f64; 3],
+    pub male_success: [f64; 3],
+    pub maturation: [f64; 3],
+}
+
+impl Locus {
+    fn value(&self, component: Component, genotype: Genotype) -> f64 {
+        let values = match component {
+            Component::EggSurvival => &self.egg_survival,
+            Component::FemaleEggs => &self.female_eggs,
+            Component::MaleSuccess => &self.male_success,
+            Component::Maturation => &self.maturation,
+        };
+
+        values[genotype_index(genotype)]
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Epistasis {
+    pub component: Component,
+    // Locus indices, None for the focal inversion
+    pub loci: [Option<usize>; 2],
+    pub genotypes: [Genotype; 2],
+    pub value: f64,
+}
+
+pub struct Loci {
+    pub loci: Vec<Locus>,
+    combination: LocusCombination,
+    epistasis: Vec<Epistasis>,
+    x_linked: bool,
+}
+
+impl Loci {
+    pub fn new(
+        loci_file: Option<&str>,
+        epistasis_file: Option<&str>,
+        combination: LocusCombination,
+        inheritance: Inheritance,
+    ) -> Loci {
+        let loci = match loci_file {
+            Some(path) => read_loci(path),
+            None => Vec::new(),
+        };
+
+        let epistasis = match epistasis_file {
+            Some(path) => read_epistasis(path, &loci),
+            None => Vec::new(),
+        };
+
+        Loci {
+            loci,
+            combination,
+            epistasis,
+            x_linked: inheritance == Inheritance::XLinked,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.loci.is_empty()
+    }
+
+    pub fn random_chromosome(&self, rng: &mut SimulationRng) -> u32 {
+        // Starting alleles drawn from the frequency of B at each locus
+        let mut chromosome = 0;
+
+        for (i, locus) in self.loci.iter().enumerate() {
+            if rng.gen::<f64>() < locus.frequency_b {
+                chromosome |= 1 << i;
+            }
+        }
+
+        chromosome
+    }
+
+    pub fn gamete(&self, parent: &Fly, chromosome: usize, rng: &mut SimulationRng) -> u32 {
+        // Return the loci transmitted along with the focal inversion of
+        // `chromosome`, switching chromosome at each recombination
+        let mut current = chromosome;
+        let mut gamete = 0;
+
+        for (i, locus) in self.loci.iter().enumerate() {
+            if rng.gen::<f64>() < locus.recombination {
+                current = 1 - current;
+            }
+
+            gamete |= parent.loci[current] & (1 << i);
+        }
+
+        gamete
+    }
+
+    fn hemizygous(&self, fly: &Fly) -> bool {
+        // Males carry a single chromosome under X linkage
+        self.x_linked && fly.sex == Sex::Male
+    }
+
+    pub fn genotype(&self, fly: &Fly, locus: usize) -> Genotype {
+        match (fly.loci[0] >> locus & 1, fly.loci[1] >> locus & 1) {
+            (0, 0) => Genotype::AA,
+            (1, 1) => Genotype::BB,
+            _ => Genotype::AB,
+        }
+    }
+
+    fn locus_genotype(&self, fly: &Fly, locus: Option<usize>) -> Genotype {
+        match locus {
+            Some(i) => self.genotype(fly, i),
+            None => fly.genotype,
+        }
+    }
+
+    pub fn combine(&self, component: Component, base: f64, fly: &Fly) -> f64 {
+        // Combine the value of the focal inversion with those of the other
+        // loci and their epistatic interactions
+        if !self.is_active() {
+            return base;
+        }
+
+        let mut value = base;
+
+        for (i, locus) in self.loci.iter().enumerate() {
+            let locus_value = locus.value(component, self.genotype(fly, i));
+
+            match self.combination {
+                LocusCombination::Additive => value += locus_value - 1.0,
+                LocusCombination::Multiplicative => value *= locus_value,
+            }
+        }
+
+        for e in self.epistasis.iter().filter(|e| e.component == component) {
+            if self.locus_genotype(fly, e.loci[0]) == e.genotypes[0]
+                && self.locus_genotype(fly, e.loci[1]) == e.genotypes[1]
+            {
+                match self.combination {
+                    LocusCombination::Additive => value += e.value,
+                    LocusCombination::Multiplicative => value *= e.value,
+                }
+            }
+        }
+
+        value.max(0.0)
+    }
+
+    pub fn statistics(&self, flies: &[Fly]) -> Vec<(f64, f64)> {
+        // Frequency of allele B at each locus and its linkage
+        // disequilibrium with arrangement B of the focal inversion.
+        // Hemizygous males under X linkage count for one chromosome
+        let number_chromosomes: usize = flies
+            .iter()
+            .map(|f| if self.hemizygous(f) { 1 } else { 2 })
+            .sum();
+
+        (0..self.loci.len())
+            .map(|i| {
+                let mut count_b = 0;
+                let mut count_arrangement_b = 0;
+                let mut count_both_b = 0;
+
+                for fly in flies.iter() {
+                    let arrangements = chromosome_arrangements(fly.genotype);
+
+                    let chromosomes = if self.hemizygous(fly) { 1 } else { 2 };
+
+                    for (arrangement, chromosome) in
+                        arrangements.iter().zip(fly.loci.iter()).take(chromosomes)
+                    {
+                        let allele_b = chromosome & (1 << i) != 0;
+                        let arrangement_b = *arrangement == 'B';
+
+                        count_b += allele_b as usize;
+                        count_arrangement_b += arrangement_b as usize;
+                        count_both_b += (allele_b && arrangement_b) as usize;
+                    }
+                }
+
+                let n = number_chromosomes as f64;
+                let frequency_b = count_b as f64 / n;
+                let frequency_arrangement_b = count_arrangement_b as f64 / n;
+                let disequilibrium =
+                    count_both_b as f64 / n - frequency_b * frequency_arrangement_b;
+
+                (frequency_b, disequilibrium)
+            })
+            .collect()
+    }
+}
+
+//// Functions
+fn read_loci(path: &str) -> Vec<Locus> {
+    // Read one locus per row. The header gives the column names: `name`,
+    // `recombination`, `frequency_b` and relative values named like
+    // `egg_survival_aa`, `female_eggs_ab`, `male_success_bb` or
+    // `maturation_aa`. Missing columns keep their default, unlinked and
+    // neutral, values
+    let content = fs::read_to_string(path).unwrap_or_else(|_| {
+        eprintln!("Cannot read loci file: {}", path);
+        process::exit(1);
+    });
+
+    let mut lines = content
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'));
+
+    let header: Vec<&str> = match lines.next() {
+        Some(line) => line.split(',').map(|v| v.trim()).collect(),
+        None => {
+            eprintln!("Loci file is empty: {}", path);
+            process::exit(1);
+        }
+    };
+
+    let mut loci = Vec::new();
+
+    for line in lines {
+        let values: Vec<&str> = line.split(',').map(|v| v.trim()).collect();
+
+        if values.len() != header.len() {
+            eprintln!("Loci file rows need {} values: {}", header.len(), line);
+            process::exit(1);
+        }
+
+        let mut locus = Locus {
+            name: format!("locus{}", loci.len() + 1),
+            recombination: 0.5,
+            frequency_b: 0.5,
+            egg_survival: [1.0; 3],
+            female_eggs: [1.0; 3],
+            male_success: [1.0; 3],
+            maturation: [1.0; 3],
+        };
+
+        for (name, value) in header.iter().zip(values.iter()) {
+            if value.is_empty() {
+                continue;
+            }
+
+            if *name == "name" {
+                locus.name = value.to_string();
+                continue;
+            }
+
+            let value = value.parse::<f64>().expect("Cannot parse locus value");
+
+            match *name {
+                "recombination" => locus.recombination = value,
+                "frequency_b" => locus.frequency_b = value,
+                "egg_survival_aa" => locus.egg_survival[0] = value,
+                "egg_survival_ab" => locus.egg_survival[1] = value,
+                "egg_survival_bb" => locus.egg_survival[2] = value,
+                "female_eggs_aa" => locus.female_eggs[0] = value,
+                "female_eggs_ab" => locus.female_eggs[1] = value,
+                "female_eggs_bb" => locus.female_eggs[2] = value,
+                "male_success_aa" => locus.male_success[0] = value,
+                "male_success_ab" => locus.male_success[1] = value,
+                "male_success_bb" => locus.male_success[2] = value,
+                "maturation_aa" => locus.maturation[0] = value,
+                "maturation_ab" => locus.maturation[1] = value,
+                "maturation_bb" => locus.maturation[2] = value,
+                _ => {
+                    eprintln!("Unknown loci file column: {}", name);
+                    process::exit(1);
+                }
+            }
+        }
+
+        loci.push(locus);
+    }
+
+    if loci.len() > MAX_LOCI {
+        eprintln!("At most {} additional loci can be simulated", MAX_LOCI);
+        process::exit(1);
+    }
+
+    loci
+}
+
+fn read_epistasis(path: &str, loci: &[Locus]) -> Vec<Epistasis> {
+    // Read `component,locus1,genotype1,locus2,genotype2,value` rows. The
+    // value applies to individuals carrying both genotypes, added or
+    // multiplied depending on how loci are combined
+    let content = fs::read_to_string(path).unwrap_or_else(|_| {
+        eprintln!("Cannot read epistasis file: {}", path);
+        process::exit(1);
+    });
+
+    let locus_index = |name: &str| -> Option<usize> {
+        if name == FOCAL_LOCUS {
+            return None;
+        }
+
+        match loci.iter().position(|l| l.name == name) {
+            Some(i) => Some(i),
+            None => {
+                eprintln!("Unknown locus in epistasis file: {}", name);
+                process::exit(1);
+            }
+        }
+    };
+
+    let genotype = |name: &str| -> Genotype {
+        match name {
+            "AA" => Genotype::AA,
+            "AB" => Genotype::AB,
+            "BB" => Genotype::BB,
+            _ => {
+                eprintln!("Unknown genotype in epistasis file: {}", name);
+                process::exit(1);
+            }
+        }
+    };
+
+    let mut epistasis = Vec::new();
+
+    for line in content.lines() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let values: Vec<&str> = line.split(',').map(|v| v.trim()).collect();
+
+        if values.len() != 6 {
+            eprintln!("Epistasis file rows need 6 values: {}", line);
+            process::exit(1);
+        }
+
+        let component = values[0].parse::<Component>().unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        });
+
+        epistasis.push(Epistasis {
+            component,
+            loci: [locus_index(values[1]), locus_index(values[3])],
+            genotypes: [genotype(values[2]), genotype(values[4])],
+            value: values[5]
+                .parse::<f64>()
+                .expect("Cannot parse epistasis value"),
+        });
+    }
+
+    epistasis
+}
@@ -1,18 +1,46 @@
 //#![allow(warnings)]
+#![allow(clippy::four_forward_slashes)]
 //// Modules
+mod abc;
+mod body_size;
+mod checkpoint;
+mod demography;
+mod effective_size;
+mod freq_dep;
+mod hardy_weinberg;
+mod loci;
+mod maternal;
+mod metapopulation;
+mod mle;
+mod neutral;
+mod parameters;
+mod pedigree;
+mod random;
+mod sensitivity;
+mod simulation;
+mod sweep;
+mod temperature;
+mod tree_sequence;
+mod vcf;
+use hardy_weinberg::HardyWeinberg;
+use maternal::Mother;
+use metapopulation::{Migration, MigrationModel, MigrationStage};
+use parameters::Parameters;
+use random::SimulationRng;
+use simulation::OutputLayout;
+
 extern crate clap;
-use clap::{App, Arg};
+use clap::{App, AppSettings, Arg, SubCommand};
 
 extern crate rand;
-use rand::distributions::Uniform;
+use rand::distributions::Poisson;
 use rand::prelude::*;
 use rand::seq::SliceRandom;
 use rand::Rng;
 
 use std::collections::HashMap;
-use std::fs::File;
 use std::io::Write;
-use std::process;
+use std::str::FromStr;
 use std::vec::Vec;
 
 //// Enums
@@ -56,7 +84,6 @@ enum Lifestage {
     Adult,
 }
 
-//// Structs
 impl std::fmt::Display for Lifestage {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         let printable = match *self {
@@ -67,10 +94,125 @@ impl std::fmt::Display for Lifestage {
     }
 }
 
-#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum MatesDistribution {
+    Fixed,
+    Poisson,
+}
+
+impl FromStr for MatesDistribution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fixed" => Ok(MatesDistribution::Fixed),
+            "poisson" => Ok(MatesDistribution::Poisson),
+            _ => Err(format!("Unknown mates distribution: {}", s)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum SpermPrecedence {
+    Last,
+    First,
+    Raffle,
+}
+
+impl FromStr for SpermPrecedence {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "last" => Ok(SpermPrecedence::Last),
+            "first" => Ok(SpermPrecedence::First),
+            "raffle" => Ok(SpermPrecedence::Raffle),
+            _ => Err(format!("Unknown sperm precedence: {}", s)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum DensityModel {
+    None,
+    BevertonHolt,
+    Ricker,
+}
+
+impl FromStr for DensityModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(DensityModel::None),
+            "beverton-holt" => Ok(DensityModel::BevertonHolt),
+            "ricker" => Ok(DensityModel::Ricker),
+            _ => Err(format!("Unknown density model: {}", s)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum Inheritance {
+    Autosomal,
+    XLinked,
+}
+
+impl FromStr for Inheritance {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "autosomal" => Ok(Inheritance::Autosomal),
+            "x-linked" => Ok(Inheritance::XLinked),
+            _ => Err(format!("Unknown inheritance: {}", s)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum Expression {
+    Both,
+    Females,
+    Males,
+}
+
+impl FromStr for Expression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "both" => Ok(Expression::Both),
+            "females" => Ok(Expression::Females),
+            "males" => Ok(Expression::Males),
+            _ => Err(format!("Unknown expression: {}", s)),
+        }
+    }
+}
+
+//// Structs
+#[derive(Copy, Clone, PartialEq, Debug)]
 struct Fly {
     sex: Sex,
     genotype: Genotype,
+    // Neutral sites of both chromosomes, see `neutral`
+    haplotypes: [u64; 2],
+    // Additional loci of both chromosomes, see `loci`
+    loci: [u32; 2],
+    // Own maturation time and environment duration, set at maturation
+    maturation: f64,
+    environment: f64,
+    // Mother of eggs, see `maternal`
+    mother: Option<Mother>,
+    // Male body size, see `body_size`
+    size: f64,
+    // Identifier given to adults and identifiers of both parents, 0 when
+    // unknown, see `effective_size`
+    id: u64,
+    parents: [u64; 2],
+    // Parent identifier and parent chromosome copied by each chromosome at
+    // the inversion, see `pedigree`
+    origins: [(u64, u8); 2],
 }
 
 impl std::fmt::Display for Fly {
@@ -96,37 +238,49 @@ fn create_first_generation(
     n: u32,
     psexes: &[ProportionSexe],
     pgenotypes: &[ProportionGenotype],
+    rng: &mut SimulationRng,
 ) -> Vec<Fly> {
-    let mut rng = rand::thread_rng();
     let mut samples = Vec::new();
 
     // Create adults with random sex and genotype using proportions
     for _ in 0..n {
         let sex = psexes
-            .choose_weighted(&mut rng, |item| item.proportion)
+            .choose_weighted(rng, |item| item.proportion)
             .unwrap()
             .sex;
 
         let genotype = pgenotypes
-            .choose_weighted(&mut rng, |item| item.proportion)
+            .choose_weighted(rng, |item| item.proportion)
             .unwrap()
             .genotype;
 
-        samples.push(Fly { sex, genotype });
+        samples.push(Fly {
+            sex,
+            genotype,
+            haplotypes: [0, 0],
+            loci: [0, 0],
+            maturation: 0.0,
+            environment: 0.0,
+            mother: None,
+            size: 0.0,
+            id: 0,
+            parents: [0, 0],
+            origins: [(0, 0), (0, 0)],
+        });
     }
 
     samples
 }
 
-fn allele_from_parent(p: Fly) -> char {
-    // Return a random allele from a parent
+fn allele_from_parent(p: Fly, transmission_b: f64, rng: &mut SimulationRng) -> char {
+    // Return a random allele from a parent. AB parents transmit B with
+    // probability `transmission_b`, 0.5 without meiotic drive
     match p.genotype {
         Genotype::AA => 'A',
         Genotype::AB => {
-            let mut rng = rand::thread_rng();
             let random_number: f64 = rng.gen();
 
-            if random_number < 0.5 {
+            if random_number < 1.0 - transmission_b {
                 'A'
             } else {
                 'B'
@@ -136,6 +290,132 @@ fn allele_from_parent(p: Fly) -> char {
     }
 }
 
+fn mutate_allele(
+    allele: char,
+    rate_a_to_b: f64,
+    rate_b_to_a: f64,
+    rng: &mut SimulationRng,
+) -> char {
+    // Return the transmitted allele after recurrent mutation
+    let random_number: f64 = rng.gen();
+
+    match allele {
+        'A' if random_number < rate_a_to_b => 'B',
+        'B' if random_number < rate_b_to_a => 'A',
+        _ => allele,
+    }
+}
+
+fn introduce_allele(
+    flies: &mut [Fly],
+    allele: char,
+    inheritance: Inheritance,
+    rng: &mut SimulationRng,
+) {
+    // Replace one allele of a random individual by `allele`, turning a
+    // homozygote into a heterozygote or a heterozygote into a homozygote.
+    // Hemizygous males under X linkage have their only allele replaced
+    let (from, to) = match allele {
+        'A' => ([Genotype::BB, Genotype::AB], [Genotype::AB, Genotype::AA]),
+        _ => ([Genotype::AA, Genotype::AB], [Genotype::AB, Genotype::BB]),
+    };
+
+    let candidates: Vec<usize> = (0..flies.len())
+        .filter(|i| from.contains(&flies[*i].genotype))
+        .collect();
+
+    if let Some(i) = candidates.choose(rng) {
+        if inheritance == Inheritance::XLinked && flies[*i].sex == Sex::Male {
+            flies[*i].genotype = genotype_from_alleles(allele, allele);
+        } else {
+            let position = from.iter().position(|g| *g == flies[*i].genotype).unwrap();
+            flies[*i].genotype = to[position];
+        }
+    }
+}
+
+fn number_of_mates(
+    number_mates: f64,
+    mates_distribution: MatesDistribution,
+    rng: &mut SimulationRng,
+) -> usize {
+    // Return how many males a female mates with. Every female mates at
+    // least once, so the Poisson draw is shifted by one to keep
+    // `number_mates` as the mean
+    match mates_distribution {
+        MatesDistribution::Fixed => number_mates.round().max(1.0) as usize,
+        MatesDistribution::Poisson => {
+            let extra_mates = number_mates - 1.0;
+
+            if extra_mates > 0.0 {
+                1 + Poisson::new(extra_mates).sample(rng) as usize
+            } else {
+                1
+            }
+        }
+    }
+}
+
+fn father_from_mates(
+    // Return the male siring one egg among the mates of a female, listed
+    // in mating order
+    mates: &[Fly],
+    sperm_precedence: SpermPrecedence,
+    sperm_precedence_strength: f64,
+    male_success: impl Fn(&Fly) -> f64,
+    rng: &mut SimulationRng,
+) -> Fly {
+    if mates.len() == 1 {
+        return mates[0];
+    }
+
+    match sperm_precedence {
+        SpermPrecedence::Raffle => *mates
+            .choose_weighted(rng, |mate| male_success(mate))
+            .unwrap(),
+
+        SpermPrecedence::Last | SpermPrecedence::First => {
+            // The favoured male sires the egg with probability
+            // `sperm_precedence_strength`, otherwise one of the other
+            // mates is picked at random
+            let favoured = match sperm_precedence {
+                SpermPrecedence::First => 0,
+                _ => mates.len() - 1,
+            };
+            let random_number: f64 = rng.gen();
+
+            if random_number < sperm_precedence_strength {
+                mates[favoured]
+            } else {
+                let other = rng.gen_range(0, mates.len() - 1);
+
+                if other < favoured {
+                    mates[other]
+                } else {
+                    mates[other + 1]
+                }
+            }
+        }
+    }
+}
+
+fn density_survival(
+    // Return the proportion of eggs escaping larval competition given
+    // the number of eggs laid, each weighted by its competitive effect
+    eggs: &[Fly],
+    density_model: DensityModel,
+    competition: &HashMap<Genotype, f64>,
+    density_scale: f64,
+) -> f64 {
+    let density: f64 = eggs.iter().map(|egg| competition[&egg.genotype]).sum();
+
+    match density_model {
+        DensityModel::None => 1.0,
+        DensityModel::BevertonHolt => 1.0 / (1.0 + density / density_scale),
+        DensityModel::Ricker => (-density / density_scale).exp(),
+    }
+}
+
 fn genotype_from_alleles(a1: char, a2: char) -> Genotype {
     // Create a Genotype from two alleles passed as chars
     if a1 == 'A' && a2 == 'A' {
@@ -147,6 +427,15 @@ fn genotype_from_alleles(a1: char, a2: char) -> Genotype {
     }
 }
 
+fn genotype_index(genotype: Genotype) -> usize {
+    // Position of a genotype in arrays ordered as AA, AB, BB
+    match genotype {
+        Genotype::AA => 0,
+        Genotype::AB => 1,
+        Genotype::BB => 2,
+    }
+}
+
 fn get_genotype_proportions(samples: &[Fly]) -> [f64; 3] {
     // Return array of 3 values containing the proportion of
     // AA, AB, and BB genotypes
@@ -177,12 +466,16 @@ fn report_genotypes(
     samples: &[Fly],
     generation: u32,
     lifestage: Lifestage,
-    outfile: &mut File,
+    outfile: &mut dyn Write,
     quiet: bool,
+    population_size: Option<(usize, f64)>,
+    hwe: &[HardyWeinberg],
 ) {
-    let genotypes = get_genotype_proportions(&samples);
+    // `hwe` holds diagnostics of eggs and adults so far, the last one being
+    // those of `samples`
+    let genotypes = get_genotype_proportions(samples);
     if !quiet {
-        println!(
+        print!(
             "{}\t{}\t{}\t{:.3}\t{:.3}\t{:.3}",
             generation,
             lifestage,
@@ -191,6 +484,15 @@ fn report_genotypes(
             genotypes[1],
             genotypes[2]
         );
+
+        if let Some(h) = hwe.last() {
+            print!(
+                "\t{:.3}\t{:.3}\t{:.3}\t{:.3e}",
+                h.observed_heterozygosity, h.expected_heterozygosity, h.fis, h.p_value
+            );
+        }
+
+        println!();
     }
 
     // Report to file
@@ -204,10 +506,36 @@ fn report_genotypes(
             .as_bytes(),
         ),
 
-        Lifestage::Adult => outfile
-            .write(format!("{},{},{}\n", genotypes[0], genotypes[1], genotypes[2]).as_bytes()),
+        Lifestage::Adult => {
+            outfile.write(format!("{},{},{}", genotypes[0], genotypes[1], genotypes[2]).as_bytes())
+        }
     }
     .unwrap();
+
+    // Adults end the line, optionally with the number of eggs laid,
+    // number of adults, and carrying capacity of the generation, then Hardy
+    // Weinberg diagnostics of eggs and adults
+    if lifestage == Lifestage::Adult {
+        if let Some((number_eggs, capacity)) = population_size {
+            outfile
+                .write_all(format!(",{},{},{}", number_eggs, samples.len(), capacity).as_bytes())
+                .unwrap();
+        }
+
+        for h in hwe.iter() {
+            outfile
+                .write_all(
+                    format!(
+                        ",{},{},{},{}",
+                        h.observed_heterozygosity, h.expected_heterozygosity, h.fis, h.p_value
+                    )
+                    .as_bytes(),
+                )
+                .unwrap();
+        }
+
+        outfile.write_all(b"\n").unwrap();
+    }
 }
 
 //// Main
@@ -379,6 +707,144 @@ fn main() {
                 .help("Intensity of frequency dependence on males [0, 1] (default=0.1)")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("male_freq_dep_model")
+                .long("male-freq-dep-model")
+                .value_name("STRING")
+                .help("Frequency dependence model on males [linear, rare-male, negative, table] (default=linear)")
+                .possible_values(&["linear", "rare-male", "negative", "table"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("male_freq_dep_exponent")
+                .long("male-freq-dep-exponent")
+                .value_name("FLOAT")
+                .help("Exponent of the rare-male frequency dependence model [>= 0] (default=1.0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("male_freq_dep_table")
+                .long("male-freq-dep-table")
+                .value_name("STRING")
+                .help("CSV file with frequency,AA,AB,BB rows for the table model")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("egg_density_model")
+                .long("egg-density-model")
+                .value_name("STRING")
+                .help("Density dependence of egg survival, replacing the truncation to number-eggs-per-generation, which becomes the density scale [none, beverton-holt, ricker] (default=none)")
+                .possible_values(&["none", "beverton-holt", "ricker"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("competition_aa")
+                .long("competition-aa")
+                .value_name("FLOAT")
+                .help("Competitive effect of AA larvae on density [>= 0] (default=1.0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("competition_ab")
+                .long("competition-ab")
+                .value_name("FLOAT")
+                .help("Competitive effect of AB larvae on density [>= 0] (default=1.0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("competition_bb")
+                .long("competition-bb")
+                .value_name("FLOAT")
+                .help("Competitive effect of BB larvae on density [>= 0] (default=1.0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("carrying_capacity_model")
+                .long("carrying-capacity-model")
+                .value_name("STRING")
+                .help("Variation of carrying capacity around number-eggs-per-generation [constant, periodic, schedule] (default=constant)")
+                .possible_values(&["constant", "periodic", "schedule"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("carrying_capacity_amplitude")
+                .long("carrying-capacity-amplitude")
+                .value_name("FLOAT")
+                .help("Relative amplitude of periodic carrying capacity [0, 1] (default=0.5)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("carrying_capacity_period")
+                .long("carrying-capacity-period")
+                .value_name("FLOAT")
+                .help("Period of carrying capacity in generations [> 0] (default=20)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("carrying_capacity_schedule")
+                .long("carrying-capacity-schedule")
+                .value_name("STRING")
+                .help("CSV file with generation,capacity rows for the schedule model")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("report_population_size")
+                .long("report-population-size")
+                .value_name("BOOL")
+                .help("Add numbers of eggs and adults and carrying capacity to output (default=false)")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("female_freq_dep_coef")
+                .long("female-freq-dep-coef")
+                .value_name("FLOAT")
+                .help("Decrease of female fecundity with the frequency of her genotype [0, 1] (default=0.0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("female_preference")
+                .long("female-preference")
+                .value_name("FLOATS")
+                .help("Female by male genotype preferences, 9 values by row (default=1,1,1,1,1,1,1,1,1)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("assortative_mating")
+                .long("assortative-mating")
+                .value_name("FLOAT")
+                .help("Added preference for males of the female's genotype [>= -1] (default=0.0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("number_mates")
+                .long("number-mates")
+                .value_name("FLOAT")
+                .help("Number of mates per female, mean if Poisson distributed [>= 1] (default=1)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("mates_distribution")
+                .long("mates-distribution")
+                .value_name("STRING")
+                .help("Distribution of number of mates per female [fixed, poisson] (default=fixed)")
+                .possible_values(&["fixed", "poisson"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("sperm_precedence")
+                .long("sperm-precedence")
+                .value_name("STRING")
+                .help("Paternity of eggs among mates [last, first, raffle] (default=last)")
+                .possible_values(&["last", "first", "raffle"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("sperm_precedence_strength")
+                .long("sperm-precedence-strength")
+                .value_name("FLOAT")
+                .help("Proportion of eggs sired by the last or first mate [0, 1] (default=1.0)")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("female_maturation_days")
                 .long("female-maturation-days")
@@ -429,612 +895,787 @@ fn main() {
                 .takes_value(true),
         )
         .arg(
-            Arg::with_name("stop_when_fixated")
-                .long("stop-when-fixated")
-                .value_name("BOOL")
-                .help("Stop simulation if only one allele remains (default=false)")
-                .takes_value(false),
+            Arg::with_name("mutation_rate_a_to_b")
+                .long("mutation-rate-a-to-b")
+                .value_name("FLOAT")
+                .help("Probability that a transmitted A allele mutates to B [0, 1] (default=0.0)")
+                .takes_value(true),
         )
         .arg(
-            Arg::with_name("quiet")
-                .long("quiet")
-                .short("q")
-                .value_name("BOOL")
-                .help("Do no report progress on screen (default=false)")
-                .takes_value(false),
+            Arg::with_name("mutation_rate_b_to_a")
+                .long("mutation-rate-b-to-a")
+                .value_name("FLOAT")
+                .help("Probability that a transmitted B allele mutates to A [0, 1] (default=0.0)")
+                .takes_value(true),
         )
-        .get_matches();
-
-    // Convert parameters to wanted types
-    let output_file = matches
-        .value_of("output_file")
-        .expect("Cannot create output file");
-
-    let experiment_name = matches
-        .value_of("experiment_name")
-        .unwrap_or("unnamed_experiment");
-
-    let number_generations = matches
-        .value_of("number_generations")
-        .unwrap_or("5")
-        .parse::<u32>()
-        .unwrap();
-
-    let proportion_females = matches
-        .value_of("proportion_females")
-        .unwrap_or("0.5")
-        .parse::<f64>()
-        .unwrap();
-
-    let number_eggs_per_generation = matches
-        .value_of("number_eggs_per_generation")
-        .unwrap_or("1000")
-        .parse::<usize>()
-        .unwrap();
-
-    let number_eggs_per_female = matches
-        .value_of("number_eggs_per_female")
-        .unwrap_or("50")
-        .parse::<f64>()
-        .unwrap();
-
-    let proportion_aa = matches
-        .value_of("proportion_aa")
-        .unwrap_or("0.07")
-        .parse::<f64>()
-        .unwrap();
-
-    let proportion_bb = matches
-        .value_of("proportion_bb")
-        .unwrap_or("0.44")
-        .parse::<f64>()
-        .unwrap();
-
-    let survival_global = matches
-        .value_of("survival_global")
-        .unwrap_or("0.3")
-        .parse::<f64>()
-        .unwrap();
-
-    let survival_females_aa = matches
-        .value_of("survival_females_aa")
-        .unwrap_or("0.71")
-        .parse::<f64>()
-        .unwrap();
-
-    let survival_females_ab = matches
-        .value_of("survival_females_ab")
-        .unwrap_or("0.9")
-        .parse::<f64>()
-        .unwrap();
-
-    let survival_females_bb = matches
-        .value_of("survival_females_bb")
-        .unwrap_or("1.0")
-        .parse::<f64>()
-        .unwrap();
-
-    let survival_males_aa = matches
-        .value_of("survival_males_aa")
-        .unwrap_or("0.81")
-        .parse::<f64>()
-        .unwrap();
-
-    let survival_males_ab = matches
-        .value_of("survival_males_ab")
-        .unwrap_or("1.0")
-        .parse::<f64>()
-        .unwrap();
-
-    let survival_males_bb = matches
-        .value_of("survival_males_bb")
-        .unwrap_or("0.88")
-        .parse::<f64>()
-        .unwrap();
-
-    let female_eggs_aa = matches
-        .value_of("female_eggs_aa")
-        .unwrap_or("1.0")
-        .parse::<f64>()
-        .unwrap();
-
-    let female_eggs_ab = matches
-        .value_of("female_eggs_ab")
-        .unwrap_or("0.97")
-        .parse::<f64>()
-        .unwrap();
-
-    let female_eggs_bb = matches
-        .value_of("female_eggs_bb")
-        .unwrap_or("0.87")
-        .parse::<f64>()
-        .unwrap();
-
-    let male_success_aa = matches
-        .value_of("male_success_aa")
-        .unwrap_or("1.0")
-        .parse::<f64>()
-        .unwrap();
-
-    let male_success_ab = matches
-        .value_of("male_success_ab")
-        .unwrap_or("0.55")
-        .parse::<f64>()
-        .unwrap();
-
-    let male_success_bb = matches
-        .value_of("male_success_bb")
-        .unwrap_or("0.1")
-        .parse::<f64>()
-        .unwrap();
-
-    let male_freq_dep_coef = matches
-        .value_of("male_freq_dep_coef")
-        .unwrap_or("0.1")
-        .parse::<f64>()
-        .unwrap();
-
-    let female_maturation_days = matches
-        .value_of("female_maturation_days")
-        .unwrap_or("8.8")
-        .parse::<f64>()
-        .unwrap();
-
-    let male_maturation_days_aa = matches
-        .value_of("male_maturation_days_aa")
-        .unwrap_or("12.8")
-        .parse::<f64>()
-        .unwrap();
-
-    let male_maturation_days_ab = matches
-        .value_of("male_maturation_days_ab")
-        .unwrap_or("10.3")
-        .parse::<f64>()
-        .unwrap();
-
-    let male_maturation_days_bb = matches
-        .value_of("male_maturation_days_bb")
-        .unwrap_or("8.7")
-        .parse::<f64>()
+        .arg(
+            Arg::with_name("introduction_generation")
+                .long("introduction-generation")
+                .value_name("INT")
+                .help("Generation at which one copy of introduction-allele is given to an adult of the first deme")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("introduction_allele")
+                .long("introduction-allele")
+                .value_name("STRING")
+                .help("Allele introduced at introduction-generation [A, B] (default=B)")
+                .possible_values(&["A", "B"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("neutral_sites")
+                .long("neutral-sites")
+                .value_name("INT")
+                .help("Number of neutral sites along the chromosome, reported in a _neutral output file [0, 64] (default=0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("inversion_start")
+                .long("inversion-start")
+                .value_name("FLOAT")
+                .help("Start of the inversion along the chromosome [0, 1] (default=0.25)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("inversion_end")
+                .long("inversion-end")
+                .value_name("FLOAT")
+                .help("End of the inversion along the chromosome [0, 1] (default=0.75)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("recombination_rate")
+                .long("recombination-rate")
+                .value_name("FLOAT")
+                .help("Mean number of crossovers per chromosome per meiosis [>= 0] (default=1.0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("gene_flux_rate")
+                .long("gene-flux-rate")
+                .value_name("FLOAT")
+                .help("Probability that a site inside the inversion is exchanged in AB individuals [0, 1] (default=0.0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("neutral_mutation_rate")
+                .long("neutral-mutation-rate")
+                .value_name("FLOAT")
+                .help("Mutation rate per neutral site per generation [0, 1] (default=0.0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("loci_file")
+                .long("loci-file")
+                .value_name("STRING")
+                .help("CSV file with a header of column names and one row per additional biallelic locus, with relative egg survival, female eggs, male success and maturation time per genotype")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("epistasis_file")
+                .long("epistasis-file")
+                .value_name("STRING")
+                .help("CSV file of component,locus1,genotype1,locus2,genotype2,value epistatic effects")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("locus_combination")
+                .long("locus-combination")
+                .value_name("STRING")
+                .help("Combination of fitness values across loci [additive, multiplicative] (default=multiplicative)")
+                .possible_values(&["additive", "multiplicative"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("inheritance")
+                .long("inheritance")
+                .value_name("STRING")
+                .help("Inheritance of the inversion, males being hemizygous when x-linked [autosomal, x-linked] (default=autosomal)")
+                .possible_values(&["autosomal", "x-linked"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("survival_expression")
+                .long("survival-expression")
+                .value_name("STRING")
+                .help("Sex expressing genotype effects on egg survival, the other sex getting their mean [both, females, males] (default=both)")
+                .possible_values(&["both", "females", "males"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("maturation_expression")
+                .long("maturation-expression")
+                .value_name("STRING")
+                .help("Sex expressing genotype effects on maturation time, the other sex getting their mean [both, females, males] (default=both)")
+                .possible_values(&["both", "females", "males"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("drive_k_females")
+                .long("drive-k-females")
+                .value_name("FLOAT")
+                .help("Proportion of B gametes transmitted by AB females, 0.5 without meiotic drive [0, 1] (default=0.5)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("drive_k_males")
+                .long("drive-k-males")
+                .value_name("FLOAT")
+                .help("Proportion of B gametes transmitted by AB males, 0.5 without meiotic drive [0, 1] (default=0.5)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("maternal_survival_aa")
+                .long("maternal-survival-aa")
+                .value_name("FLOAT")
+                .help("Relative survival of eggs from AA mothers [0, 1] (default=1.0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("maternal_survival_ab")
+                .long("maternal-survival-ab")
+                .value_name("FLOAT")
+                .help("Relative survival of eggs from AB mothers [0, 1] (default=1.0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("maternal_survival_bb")
+                .long("maternal-survival-bb")
+                .value_name("FLOAT")
+                .help("Relative survival of eggs from BB mothers [0, 1] (default=1.0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("maternal_maturation_aa")
+                .long("maternal-maturation-aa")
+                .value_name("FLOAT")
+                .help("Days added to maturation time of offspring of AA mothers (default=0.0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("maternal_maturation_ab")
+                .long("maternal-maturation-ab")
+                .value_name("FLOAT")
+                .help("Days added to maturation time of offspring of AB mothers (default=0.0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("maternal_maturation_bb")
+                .long("maternal-maturation-bb")
+                .value_name("FLOAT")
+                .help("Days added to maturation time of offspring of BB mothers (default=0.0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("maternal_maturation_slope")
+                .long("maternal-maturation-slope")
+                .value_name("FLOAT")
+                .help("Days added to offspring maturation time per day the mother matured after female-maturation-days (default=0.0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("carryover_survival")
+                .long("carryover-survival")
+                .value_name("FLOAT")
+                .help("Change in relative egg survival per relative deviation of the mother's environment duration (default=0.0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("carryover_maturation")
+                .long("carryover-maturation")
+                .value_name("FLOAT")
+                .help("Days added to offspring maturation time per day of deviation of the mother's environment duration (default=0.0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("size_model")
+                .long("size-model")
+                .value_name("STRING")
+                .help("Male maturation and success from genotype constants or from an individual body size [genotype, trait] (default=genotype)")
+                .possible_values(&["genotype", "trait"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("male_size_aa")
+                .long("male-size-aa")
+                .value_name("FLOAT")
+                .help("Mean body size of AA males (default=1.0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("male_size_ab")
+                .long("male-size-ab")
+                .value_name("FLOAT")
+                .help("Mean body size of AB males (default=0.8)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("male_size_bb")
+                .long("male-size-bb")
+                .value_name("FLOAT")
+                .help("Mean body size of BB males (default=0.6)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("male_size_sd")
+                .long("male-size-sd")
+                .value_name("FLOAT")
+                .help("Standard deviation of male body size within genotypes (default=0.05)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("size_maturation_intercept")
+                .long("size-maturation-intercept")
+                .value_name("FLOAT")
+                .help("Male maturation days at size zero, increasing linearly with size (default=2.55)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("size_maturation_slope")
+                .long("size-maturation-slope")
+                .value_name("FLOAT")
+                .help("Male maturation days added per unit of body size (default=10.25)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("size_success_exponent")
+                .long("size-success-exponent")
+                .value_name("FLOAT")
+                .help("Male reproductive success is body size to this power (default=4.5)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("development_model")
+                .long("development-model")
+                .value_name("STRING")
+                .help("Maturation after a number of days or after a thermal sum [days, degree-days] (default=days)")
+                .possible_values(&["days", "degree-days"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("temperature_model")
+                .long("temperature-model")
+                .value_name("STRING")
+                .help("Wrack temperature through generations [constant, schedule, stochastic] (default=constant)")
+                .possible_values(&["constant", "schedule", "stochastic"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("temperature")
+                .long("temperature")
+                .value_name("FLOAT")
+                .help("Wrack temperature, or its mean when stochastic, in degrees (default=20.0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("temperature_sd")
+                .long("temperature-sd")
+                .value_name("FLOAT")
+                .help("Standard deviation of stochastic wrack temperature between generations (default=0.0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("temperature_schedule")
+                .long("temperature-schedule")
+                .value_name("STRING")
+                .help("CSV file of generation,temperature rows used by the schedule temperature model")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("reference_temperature")
+                .long("reference-temperature")
+                .value_name("FLOAT")
+                .help("Temperature at which maturation takes the given maturation days (default=20.0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("development_threshold_aa")
+                .long("development-threshold-aa")
+                .value_name("FLOAT")
+                .help("Temperature below which AA individuals do not develop (default=10.0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("development_threshold_ab")
+                .long("development-threshold-ab")
+                .value_name("FLOAT")
+                .help("Temperature below which AB individuals do not develop (default=10.0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("development_threshold_bb")
+                .long("development-threshold-bb")
+                .value_name("FLOAT")
+                .help("Temperature below which BB individuals do not develop (default=10.0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("effective_size")
+                .long("effective-size")
+                .value_name("BOOL")
+                .help("Write variance and temporal effective population sizes to a file ending in _ne (default=false)")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("report_hwe")
+                .long("report-hwe")
+                .value_name("BOOL")
+                .help("Add observed and expected heterozygosity, F_IS and exact HWE test p-value of eggs and adults to output (default=false)")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("pedigree")
+                .long("pedigree")
+                .value_name("BOOL")
+                .help("Write adults with their parents and inherited chromosomes to a file ending in _pedigree (default=false)")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("tree_sequence")
+                .long("tree-sequence")
+                .value_name("BOOL")
+                .help("Write the genealogy of sampled chromosomes at the inversion as tskit text tables in files ending in _nodes, _edges and _individuals (default=false)")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("tree_sequence_samples")
+                .long("tree-sequence-samples")
+                .value_name("INT")
+                .help("Number of mature adults of the last generation sampled for the tree sequence, 0 for all (default=0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("vcf_samples")
+                .long("vcf-samples")
+                .value_name("INT")
+                .help("Number of mature adults sampled per sampled generation and written to a VCF file ending in _samples.vcf, 0 for none (default=0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("vcf_generations")
+                .long("vcf-generations")
+                .value_name("STRING")
+                .help("Comma separated generations sampled for the VCF file (default=last generation)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .value_name("INT")
+                .help("Seed of the random number generator, for reproducible runs (default=random)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("checkpoint_interval")
+                .long("checkpoint-interval")
+                .value_name("INT")
+                .help("Save the state of the run to a file ending in .checkpoint every this many generations, 0 for never (default=0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("resume")
+                .long("resume")
+                .value_name("BOOL")
+                .help("Continue the run from its checkpoint, with the same parameters and output file, or from the beginning without checkpoint (default=false)")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("number_demes")
+                .long("number-demes")
+                .value_name("INT")
+                .help("Number of identical demes, ignored when --demes is used [>= 1] (default=1)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("demes")
+                .long("demes")
+                .value_name("STRING")
+                .help("CSV file with a header of parameter names and one row of overrides per deme")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("migration_model")
+                .long("migration-model")
+                .value_name("STRING")
+                .help("Migration between demes [island, stepping-stone, gaussian, matrix] (default=island)")
+                .possible_values(&["island", "stepping-stone", "gaussian", "matrix"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("migration_rate")
+                .long("migration-rate")
+                .value_name("FLOAT")
+                .help("Proportion of individuals leaving their deme per generation [0, 1] (default=0.0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("migration_matrix")
+                .long("migration-matrix")
+                .value_name("STRING")
+                .help("CSV file with one row of migration probabilities per deme for the matrix model")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("dispersal_sigma")
+                .long("dispersal-sigma")
+                .value_name("FLOAT")
+                .help("Standard deviation of gaussian dispersal, in positions [>= 0] (default=1.0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("coastline_positions")
+                .long("coastline-positions")
+                .value_name("INT")
+                .help("Number of positions along a coastline with gaussian dispersal, reporting allele frequencies by position [>= 0] (default=0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("environment_time_end")
+                .long("environment-time-end")
+                .value_name("FLOAT")
+                .help("Environment time at the last coastline position (default=environment-time)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("migration_stage")
+                .long("migration-stage")
+                .value_name("STRING")
+                .help("Life stage that migrates [egg, adult] (default=adult)")
+                .possible_values(&["egg", "adult"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("stop_when_fixated")
+                .long("stop-when-fixated")
+                .value_name("BOOL")
+                .help("Stop simulation if only one allele remains (default=false)")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .long("quiet")
+                .short("q")
+                .value_name("BOOL")
+                .help("Do no report progress on screen (default=false)")
+                .takes_value(false),
+        )
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .subcommand(
+            SubCommand::with_name("sweep")
+                .about("Vary parameters of the base options and write or run the parameter table")
+                .arg(
+                    Arg::with_name("vary")
+                        .long("vary")
+                        .value_name("STRING")
+                        .help("Values of one parameter, as name=start:end:step or name=[a,b,c]")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("design")
+                        .long("design")
+                        .value_name("STRING")
+                        .help("Combine varied parameters [factorial, zip] (default=factorial)")
+                        .possible_values(&["factorial", "zip"])
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("ensemble")
+                        .long("ensemble")
+                        .value_name("STRING")
+                        .help("Name of the ensemble, used as prefix of experiment names (default=sweep)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("table")
+                        .long("table")
+                        .value_name("STRING")
+                        .help("Output parameter table, written to screen if absent")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("replicates")
+                        .long("replicates")
+                        .value_name("INT")
+                        .help("Run each experiment this number of times, 0 to only write the table (default=0)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("output_folder")
+                        .long("output-folder")
+                        .value_name("STRING")
+                        .help("Folder of experiment results, in one sub-folder per ensemble (default=03_simulations)")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("sensitivity")
+                .about("Sobol sensitivity indices of the final frequency of A and of fixation")
+                .arg(
+                    Arg::with_name("range")
+                        .long("range")
+                        .value_name("STRING")
+                        .help("Range of one varied parameter, as name=low:high")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("sampling")
+                        .long("sampling")
+                        .value_name("STRING")
+                        .help("Sampling of parameter sets [lhs, sobol] (default=sobol)")
+                        .possible_values(&["lhs", "sobol"])
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("samples")
+                        .long("samples")
+                        .value_name("INT")
+                        .help("Number of base samples, each needing number of ranges + 2 parameter sets (default=100)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("replicates")
+                        .long("replicates")
+                        .value_name("INT")
+                        .help("Number of runs per parameter set (default=10)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("samples_file")
+                        .long("samples-file")
+                        .value_name("STRING")
+                        .help("Output file of parameter sets with their outputs")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("indices_file")
+                        .long("indices-file")
+                        .value_name("STRING")
+                        .help("Output file of Sobol indices, written to screen if absent")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("abc")
+                .about("Approximate Bayesian Computation of parameters from observed trajectories")
+                .arg(
+                    Arg::with_name("observed")
+                        .long("observed")
+                        .value_name("STRING")
+                        .help("Observed trajectory file of generation,AA,AB,BB rows")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("prior")
+                        .long("prior")
+                        .value_name("STRING")
+                        .help("Uniform prior of one fitted parameter, as name=low:high")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("statistics")
+                        .long("statistics")
+                        .value_name("STRING")
+                        .help("Comma separated summary statistics among frequency, heterozygosity and genotypes (default=frequency,heterozygosity)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("method")
+                        .long("method")
+                        .value_name("STRING")
+                        .help("ABC method [rejection, smc] (default=rejection)")
+                        .takes_value(true)
+                        .possible_values(&["rejection", "smc"]),
+                )
+                .arg(
+                    Arg::with_name("simulations")
+                        .long("simulations")
+                        .value_name("INT")
+                        .help("Number of parameter sets drawn with rejection, or maximum per round with smc (default=1000)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("particles")
+                        .long("particles")
+                        .value_name("INT")
+                        .help("Number of accepted parameter sets (default=100)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("rounds")
+                        .long("rounds")
+                        .value_name("INT")
+                        .help("Number of smc rounds (default=5)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("quantile")
+                        .long("quantile")
+                        .value_name("FLOAT")
+                        .help("Quantile of the previous distances used as smc tolerance (default=0.5)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .value_name("STRING")
+                        .help("Output file of accepted parameter sets, written to screen if absent")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("mle")
+                .about("Maximum likelihood estimates of parameters from observed genotype counts")
+                .arg(
+                    Arg::with_name("observed")
+                        .long("observed")
+                        .value_name("STRING")
+                        .help("Observed trajectory file of generation,AA,AB,BB counts")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("parameter")
+                        .long("parameter")
+                        .value_name("STRING")
+                        .help("Range of one fitted parameter, as name=low:high")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("iterations")
+                        .long("iterations")
+                        .value_name("INT")
+                        .help("Maximum number of simplex iterations per maximisation (default=1000)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .value_name("STRING")
+                        .help("Output file of estimates, written to screen if absent")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("trajectory")
+                        .long("trajectory")
+                        .value_name("STRING")
+                        .help("Output file of expected adult proportions under the estimates")
+                        .takes_value(true),
+                ),
+        )
+        .get_matches();
+
+    // Subcommands use the other options as base parameters
+    match matches.subcommand() {
+        ("sweep", Some(sweep_matches)) => {
+            sweep::main(&Parameters::from_matches(&matches), sweep_matches);
+            return;
+        }
+        ("sensitivity", Some(sensitivity_matches)) => {
+            sensitivity::main(&Parameters::from_matches(&matches), sensitivity_matches);
+            return;
+        }
+        ("abc", Some(abc_matches)) => {
+            abc::main(&Parameters::from_matches(&matches), abc_matches);
+            return;
+        }
+        ("mle", Some(mle_matches)) => {
+            mle::main(&Parameters::from_matches(&matches), mle_matches);
+            return;
+        }
+        _ => {}
+    }
+
+    // Convert parameters to wanted types
+    let output_file = matches
+        .value_of("output_file")
+        .expect("Cannot create output file");
+
+    let params = Parameters::from_matches(&matches);
+
+    let number_demes = matches
+        .value_of("number_demes")
+        .unwrap_or("1")
+        .parse::<usize>()
         .unwrap();
 
-    let maturation_cv = matches
-        .value_of("maturation_cv")
-        .unwrap_or("0.5")
-        .parse::<f64>()
+    let migration_model = matches
+        .value_of("migration_model")
+        .unwrap_or("island")
+        .parse::<MigrationModel>()
         .unwrap();
 
-    let environment_time = matches
-        .value_of("environment_time")
-        .unwrap_or("10.0")
+    let migration_rate = matches
+        .value_of("migration_rate")
+        .unwrap_or("0.0")
         .parse::<f64>()
         .unwrap();
 
-    let environment_time_variation = matches
-        .value_of("environment_time_variation")
+    let migration_stage = matches
+        .value_of("migration_stage")
+        .unwrap_or("adult")
+        .parse::<MigrationStage>()
+        .unwrap();
+
+    let dispersal_sigma = matches
+        .value_of("dispersal_sigma")
         .unwrap_or("1.0")
         .parse::<f64>()
         .unwrap();
 
-    let stop_when_fixated = match matches.occurrences_of("stop_when_fixated") {
-        0 => false,
-        1 => true,
-        _ => false,
+    let coastline_positions = matches
+        .value_of("coastline_positions")
+        .unwrap_or("0")
+        .parse::<usize>()
+        .unwrap();
+
+    let environment_time_end = match matches.value_of("environment_time_end") {
+        Some(value) => value.parse::<f64>().unwrap(),
+        None => params.environment_time,
     };
 
-    let quiet = match matches.occurrences_of("quiet") {
-        0 => false,
-        1 => true,
-        _ => false,
+    // Parameters of each deme
+    let demes_parameters = if coastline_positions > 0 {
+        metapopulation::coastline_demes(&params, coastline_positions, environment_time_end)
+    } else {
+        match matches.value_of("demes") {
+            Some(path) => metapopulation::read_demes(path, &params),
+            None => vec![params.clone(); number_demes.max(1)],
+        }
     };
 
-    // Compute derived parameters
-    let proportion_ab = 1.0 - proportion_aa - proportion_bb;
-    let proportion_males = 1.0 - proportion_females;
-
-    // Initialize random number generation
-    let mut rng = rand::thread_rng();
-
-    //// Survival and reproduction parameters
-    // Survival from egg to adult
-    let mut egg_survival: HashMap<&Fly, f64> = HashMap::new();
-    egg_survival.insert(
-        &Fly {
-            sex: Sex::Female,
-            genotype: Genotype::AA,
-        },
-        survival_females_aa,
-    );
-    egg_survival.insert(
-        &Fly {
-            sex: Sex::Female,
-            genotype: Genotype::AB,
-        },
-        survival_females_ab,
-    );
-    egg_survival.insert(
-        &Fly {
-            sex: Sex::Female,
-            genotype: Genotype::BB,
-        },
-        survival_females_bb,
-    );
-    egg_survival.insert(
-        &Fly {
-            sex: Sex::Male,
-            genotype: Genotype::AA,
-        },
-        survival_males_aa,
-    );
-    egg_survival.insert(
-        &Fly {
-            sex: Sex::Male,
-            genotype: Genotype::AB,
-        },
-        survival_males_ab,
-    );
-    egg_survival.insert(
-        &Fly {
-            sex: Sex::Male,
-            genotype: Genotype::BB,
-        },
-        survival_males_bb,
-    );
+    // A coastline always uses gaussian dispersal
+    let (migration_model, output_layout) = if coastline_positions > 0 {
+        (MigrationModel::Gaussian, OutputLayout::Cline)
+    } else {
+        (migration_model, OutputLayout::PerDeme)
+    };
 
-    // Number of eggs per female genotype
-    let mut female_eggs: HashMap<&Fly, f64> = HashMap::new();
-    female_eggs.insert(
-        &Fly {
-            sex: Sex::Female,
-            genotype: Genotype::AA,
-        },
-        number_eggs_per_female * female_eggs_aa,
-    );
-    female_eggs.insert(
-        &Fly {
-            sex: Sex::Female,
-            genotype: Genotype::AB,
-        },
-        number_eggs_per_female * female_eggs_ab,
-    );
-    female_eggs.insert(
-        &Fly {
-            sex: Sex::Female,
-            genotype: Genotype::BB,
-        },
-        number_eggs_per_female * female_eggs_bb,
+    let migration = Migration::new(
+        migration_model,
+        migration_rate,
+        dispersal_sigma,
+        demes_parameters.len(),
+        matches.value_of("migration_matrix"),
+        migration_stage,
     );
 
-    // Male reproductive sucess per genotype
-    let mut male_success: HashMap<&Genotype, f64> = HashMap::new();
-    male_success.insert(&Genotype::AA, male_success_aa);
-    male_success.insert(&Genotype::AB, male_success_ab);
-    male_success.insert(&Genotype::BB, male_success_bb);
-
-    // Maturation time
-    let mut maturation_time: HashMap<&Fly, f64> = HashMap::new();
-    maturation_time.insert(
-        &Fly {
-            sex: Sex::Female,
-            genotype: Genotype::AA,
-        },
-        female_maturation_days,
-    );
-    maturation_time.insert(
-        &Fly {
-            sex: Sex::Female,
-            genotype: Genotype::AB,
-        },
-        female_maturation_days,
-    );
-    maturation_time.insert(
-        &Fly {
-            sex: Sex::Female,
-            genotype: Genotype::BB,
-        },
-        female_maturation_days,
+    simulation::run(
+        &params,
+        &demes_parameters,
+        &migration,
+        output_file,
+        output_layout,
     );
-    maturation_time.insert(
-        &Fly {
-            sex: Sex::Male,
-            genotype: Genotype::AA,
-        },
-        male_maturation_days_aa,
-    );
-    maturation_time.insert(
-        &Fly {
-            sex: Sex::Male,
-            genotype: Genotype::AB,
-        },
-        male_maturation_days_ab,
-    );
-    maturation_time.insert(
-        &Fly {
-            sex: Sex::Male,
-            genotype: Genotype::BB,
-        },
-        male_maturation_days_bb,
-    );
-
-    // Proportions for weighted sampling with `choose_weighted`
-    let proportion_sexes = vec![
-        ProportionSexe {
-            sex: Sex::Female,
-            proportion: proportion_females,
-        },
-        ProportionSexe {
-            sex: Sex::Male,
-            proportion: proportion_males,
-        },
-    ];
-
-    let proportion_genotypes = vec![
-        ProportionGenotype {
-            genotype: Genotype::AA,
-            proportion: proportion_aa,
-        },
-        ProportionGenotype {
-            genotype: Genotype::AB,
-            proportion: proportion_ab,
-        },
-        ProportionGenotype {
-            genotype: Genotype::BB,
-            proportion: proportion_bb,
-        },
-    ];
-
-    //// Generate first generation of eggs
-    // Create initial fly and eggs vectors
-    let mut individual_eggs: Vec<Fly> = Vec::new();
-    //let mut individual_eggs_previous: Vec<Fly> = Vec::new();
-    let mut mature_adults: Vec<Fly> = Vec::new();
-    let mut mature_females: Vec<Fly> = Vec::new();
-    let mut mature_males: Vec<Fly> = Vec::new();
-
-    let number_adults = number_eggs_per_generation as f64 * survival_global;
-    let number_adults = number_adults as u32;
-
-    let mut individual_adults =
-        create_first_generation(number_adults, &proportion_sexes, &proportion_genotypes);
-
-    // Create output file and write header
-    let mut outfile = File::create(output_file).expect("Cannot creat file");
-    outfile
-        .write_all(b"Generation,eggAA,eggAB,eggBB,adultAA,adultAB,adultBB\n")
-        .expect("Cannot write to file");
-
-    //// Iterate over generations
-    if !quiet {
-        println!("#Gen\tStage\tNum\tAA\tAB\tBB");
-    }
-
-    for gen in 0..=number_generations {
-        // Egg survival to adulthood (except generation 1)
-        if gen != 0 {
-            // Egg survival by sex and genotype
-            individual_adults.clear();
-
-            for egg in individual_eggs.iter() {
-                let random_number: f64 = rng.gen();
-
-                if random_number < egg_survival[&egg] * survival_global {
-                    individual_adults.push(*egg);
-                }
-            }
-        }
-
-        // Report egg genotypes and cleanup
-        report_genotypes(&individual_eggs, gen, Lifestage::Egg, &mut outfile, quiet);
-        //individual_eggs_previous = individual_eggs.to_vec();
-        individual_eggs.clear();
-
-        //// Survival to reproduction
-        // Environment duration
-        mature_adults.clear();
-        mature_females.clear();
-        mature_males.clear();
-        let environment_duration_min: f64 = environment_time - environment_time_variation;
-        let environment_duration_max: f64 = environment_time + environment_time_variation;
-        let environment_range = Uniform::from(environment_duration_min..environment_duration_max);
-
-        for adult in individual_adults.iter() {
-            // Environment duration
-            let environment_duration = environment_range.sample(&mut rng);
-
-            // Sample development time
-            let adult_maturation = maturation_time[&adult];
-            let adult_maturation_cv = adult_maturation * maturation_cv;
-            let adult_maturation_lower = adult_maturation - adult_maturation_cv;
-            let adult_maturation_upper = adult_maturation + adult_maturation_cv;
-            let adult_maturation_range =
-                Uniform::from(adult_maturation_lower..adult_maturation_upper);
-            let m1 = adult_maturation_range.sample(&mut rng);
-            let m2 = adult_maturation_range.sample(&mut rng);
-            let m3 = adult_maturation_range.sample(&mut rng);
-            let m = (m1 * m2 * m3).powf(1.0 / 3.0);
-
-            // Decide survival
-            if environment_duration >= m {
-                mature_adults.push(Fly {
-                    sex: adult.sex,
-                    genotype: adult.genotype,
-                });
-
-                if adult.sex == Sex::Female {
-                    mature_females.push(Fly {
-                        sex: adult.sex,
-                        genotype: adult.genotype,
-                    });
-                } else {
-                    mature_males.push(Fly {
-                        sex: adult.sex,
-                        genotype: adult.genotype,
-                    });
-                }
-            }
-        }
-
-        // Report adult genotypes
-        report_genotypes(&mature_adults, gen, Lifestage::Adult, &mut outfile, quiet);
-
-        //// Reproduction
-        // Count male genotypes
-        let number_mature_males = mature_males.len();
-        let mut male_genotype_counts: HashMap<&Genotype, f64> = HashMap::new();
-        male_genotype_counts.insert(&Genotype::AA, 0.0);
-        male_genotype_counts.insert(&Genotype::AB, 0.0);
-        male_genotype_counts.insert(&Genotype::BB, 0.0);
-
-        for male in mature_males.iter() {
-            *male_genotype_counts.entry(&male.genotype).or_insert(0.0) += 1.0;
-        }
-
-        // Make them into proportions
-        let mut male_genotype_proportions: HashMap<&Genotype, f64> = HashMap::new();
-
-        for (genotype, count) in male_genotype_counts.iter() {
-            let proportion: f64 = count / (number_mature_males as f64);
-            male_genotype_proportions.insert(genotype, proportion);
-        }
-
-        // Compute frequency dependent selection coefficient
-        let mut male_freq_dep: HashMap<&Genotype, f64> = HashMap::new();
-        let proportion_male_aa = male_genotype_proportions.get(&Genotype::AA).unwrap_or(&0.0);
-
-        male_freq_dep.insert(&Genotype::AA, 1.0);
-        male_freq_dep.insert(
-            &Genotype::AB,
-            1.0 - male_freq_dep_coef * (1.0 - proportion_male_aa) / 2.0,
-        );
-        male_freq_dep.insert(
-            &Genotype::BB,
-            1.0 - male_freq_dep_coef * (1.0 - proportion_male_aa),
-        );
-
-        // Compute male genotype probabilities for mating as function of
-        // genotype proportions, reproduction success of each genotype, and
-        // frequency dependent selection
-        let mut male_genotype_probabilities: HashMap<&Genotype, f64> = HashMap::new();
-        male_genotype_probabilities.insert(
-            &Genotype::AA,
-            male_genotype_proportions[&Genotype::AA]
-                * male_success[&Genotype::AA]
-                * male_freq_dep[&Genotype::AA],
-        );
-
-        male_genotype_probabilities.insert(
-            &Genotype::AB,
-            male_genotype_proportions[&Genotype::AB]
-                * male_success[&Genotype::AB]
-                * male_freq_dep[&Genotype::AB],
-        );
-
-        male_genotype_probabilities.insert(
-            &Genotype::BB,
-            male_genotype_proportions[&Genotype::BB]
-                * male_success[&Genotype::BB]
-                * male_freq_dep[&Genotype::BB],
-        );
-
-        // Normalize probabilities to 1.0
-        let total_coefficient: f64 = male_genotype_probabilities.values().sum();
-
-        let proportion_genotypes = vec![
-            ProportionGenotype {
-                genotype: Genotype::AA,
-                proportion: male_genotype_probabilities[&Genotype::AA] / total_coefficient,
-            },
-            ProportionGenotype {
-                genotype: Genotype::AB,
-                proportion: male_genotype_probabilities[&Genotype::AB] / total_coefficient,
-            },
-            ProportionGenotype {
-                genotype: Genotype::BB,
-                proportion: male_genotype_probabilities[&Genotype::BB] / total_coefficient,
-            },
-        ];
-
-        // Stop simulation if one of proportion_genotypes is NaN
-        for p in proportion_genotypes.iter() {
-            if p.proportion.is_nan() {
-                print!("{}\t", experiment_name);
-                report_genotypes(&mature_adults, gen, Lifestage::Adult, &mut outfile, false);
-                process::exit(0);
-            }
-        }
-
-        // Each female reproduces with one male
-        for female in mature_females.iter() {
-            // Pick weighted random mate genotype
-            let random_male_genotype = proportion_genotypes
-                .choose_weighted(&mut rng, |item| item.proportion)
-                .unwrap()
-                .genotype;
-
-            // Determine number of eggs to lay
-            let num_eggs = female_eggs[&female] as u32;
-
-            for _ in 1..=num_eggs {
-                // Get one female allele
-                let female_allele = allele_from_parent(*female);
-
-                // Get one male allele
-                let male_allele = allele_from_parent(Fly {
-                    sex: Sex::Male,
-                    genotype: random_male_genotype,
-                });
-
-                // Create egg from parent genotypes
-                let genotype = genotype_from_alleles(female_allele, male_allele);
-                let random_number: f64 = rng.gen();
-
-                let sex = if random_number < proportion_females {
-                    Sex::Female
-                } else {
-                    Sex::Male
-                };
-
-                individual_eggs.push(Fly { sex, genotype });
-            }
-        }
-
-        // Shuffle and keep number_eggs_per_generation eggs
-        individual_eggs.shuffle(&mut rng);
-        let number_eggs = individual_eggs.len();
-
-        let keep_n_eggs = if number_eggs < number_eggs_per_generation {
-            number_eggs
-        } else {
-            number_eggs_per_generation
-        };
-
-        individual_eggs = individual_eggs[..keep_n_eggs].to_vec();
-
-        // Count genotypes to decide if we end the simulation
-        // because alleles are fixated
-        if stop_when_fixated {
-            let mut count_aa = 0;
-            let mut count_ab = 0;
-            let mut count_bb = 0;
-
-            for egg in individual_eggs.iter() {
-                match egg.genotype {
-                    Genotype::AA => count_aa += 1,
-                    Genotype::AB => count_ab += 1,
-                    Genotype::BB => count_bb += 1,
-                }
-            }
-
-            // End simulation if either AA or BB alleles get fixated
-            if (count_aa == 0 || count_bb == 0) && count_ab == 0 {
-                //println!("Alleles fixated on generation {}!", gen);
-                print!("{}\t", experiment_name);
-                report_genotypes(&individual_eggs, gen, Lifestage::Egg, &mut outfile, false);
-                break;
-            } else if gen == number_generations {
-                print!("{}\t", experiment_name);
-                report_genotypes(&mature_adults, gen, Lifestage::Adult, &mut outfile, false);
-            }
-        }
-    }
 }
@@ -0,0 +1,117 @@
+//// Maternal and carry-over effects
+// Eggs remember the genotype of their mother, her own maturation time and
+// the environment duration she experienced. Egg survival and maturation
+// time can depend on the mother's genotype (maternal effects), on how late
+// she matured compared to the mean female maturation time, and on how her
+// environment differed from the mean environment (carry-over effects)
+use std::process;
+
+use crate::parameters::Parameters;
+use crate::{genotype_index, Fly, Genotype};
+
+//// Structs
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Mother {
+    pub genotype: Genotype,
+    pub maturation: f64,
+    pub environment: f64,
+}
+
+impl Mother {
+    pub fn of(female: &Fly) -> Mother {
+        Mother {
+            genotype: female.genotype,
+            maturation: female.maturation,
+            environment: female.environment,
+        }
+    }
+}
+
+pub struct MaternalEffects {
+    survival: [f64; 3],
+    maturation: [f64; 3],
+    maturation_slope: f64,
+    carryover_survival: f64,
+    carryover_maturation: f64,
+    female_maturation_days: f64,
+    environment_time: f64,
+}
+
+impl MaternalEffects {
+    pub fn new(params: &Parameters) -> MaternalEffects {
+        // Survival factors are relative survivals and cannot be negative.
+        // Maturation offsets, in days, can be negative, as long as they are
+        // numbers
+        let survivals = [
+            params.maternal_survival_aa,
+            params.maternal_survival_ab,
+            params.maternal_survival_bb,
+        ];
+        let offsets = [
+            params.maternal_maturation_aa,
+            params.maternal_maturation_ab,
+            params.maternal_maturation_bb,
+            params.maternal_maturation_slope,
+            params.carryover_survival,
+            params.carryover_maturation,
+        ];
+
+        if survivals.iter().any(|s| !s.is_finite() || *s < 0.0) {
+            eprintln!("Maternal survivals must be non-negative: {:?}", survivals);
+            process::exit(1);
+        }
+
+        if offsets.iter().any(|o| !o.is_finite()) {
+            eprintln!(
+                "Maternal and carry-over effects must be finite: {:?}",
+                offsets
+            );
+            process::exit(1);
+        }
+
+        MaternalEffects {
+            survival: [
+                params.maternal_survival_aa,
+                params.maternal_survival_ab,
+                params.maternal_survival_bb,
+            ],
+            maturation: [
+                params.maternal_maturation_aa,
+                params.maternal_maturation_ab,
+                params.maternal_maturation_bb,
+            ],
+            maturation_slope: params.maternal_maturation_slope,
+            carryover_survival: params.carryover_survival,
+            carryover_maturation: params.carryover_maturation,
+            female_maturation_days: params.female_maturation_days,
+            environment_time: params.environment_time,
+        }
+    }
+
+    pub fn survival(&self, egg: &Fly) -> f64 {
+        // Relative egg survival given the mother. The carry-over effect is
+        // per relative deviation of the mother's environment duration
+        match egg.mother {
+            Some(mother) => {
+                let deviation =
+                    (mother.environment - self.environment_time) / self.environment_time;
+
+                self.survival[genotype_index(mother.genotype)]
+                    * (1.0 + self.carryover_survival * deviation).max(0.0)
+            }
+            None => 1.0,
+        }
+    }
+
+    pub fn maturation(&self, fly: &Fly) -> f64 {
+        // Days added to maturation time given the mother
+        match fly.mother {
+            Some(mother) => {
+                self.maturation[genotype_index(mother.genotype)]
+                    + self.maturation_slope * (mother.maturation - self.female_maturation_days)
+                    + self.carryover_maturation * (mother.environment - self.environment_time)
+            }
+            None => 0.0,
+        }
+    }
+}
@@ -0,0 +1,328 @@
+//// Network of wrack-bed demes connected by migration
+// Migration is described by a matrix where row i gives the probability
+// that an individual from deme i ends up in each deme, itself included.
+// A coastline is a line of demes at regularly spaced positions with a
+// gradient of environment time and Gaussian dispersal
+use rand::distributions::{Distribution, WeightedIndex};
+
+use std::fs;
+use std::process;
+use std::str::FromStr;
+
+use crate::parameters::Parameters;
+use crate::random::SimulationRng;
+use crate::Fly;
+
+//// Enums
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum MigrationModel {
+    Island,
+    SteppingStone,
+    Gaussian,
+    Matrix,
+}
+
+impl FromStr for MigrationModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "island" => Ok(MigrationModel::Island),
+            "stepping-stone" => Ok(MigrationModel::SteppingStone),
+            "gaussian" => Ok(MigrationModel::Gaussian),
+            "matrix" => Ok(MigrationModel::Matrix),
+            _ => Err(format!("Unknown migration model: {}", s)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum MigrationStage {
+    Egg,
+    Adult,
+}
+
+impl FromStr for MigrationStage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "egg" => Ok(MigrationStage::Egg),
+            "adult" => Ok(MigrationStage::Adult),
+            _ => Err(format!("Unknown migration stage: {}", s)),
+        }
+    }
+}
+
+//// Structs
+pub struct Migration {
+    pub stage: MigrationStage,
+    pub matrix: Vec<Vec<f64>>,
+}
+
+impl Migration {
+    pub fn new(
+        model: MigrationModel,
+        rate: f64,
+        dispersal_sigma: f64,
+        number_demes: usize,
+        matrix_file: Option<&str>,
+        stage: MigrationStage,
+    ) -> Migration {
+        let matrix = match model {
+            MigrationModel::Island => island_matrix(rate, number_demes),
+            MigrationModel::SteppingStone => stepping_stone_matrix(rate, number_demes),
+            MigrationModel::Gaussian => gaussian_matrix(dispersal_sigma, number_demes),
+            MigrationModel::Matrix => match matrix_file {
+                Some(path) => read_matrix(path, number_demes),
+                None => {
+                    eprintln!("Migration model 'matrix' needs --migration-matrix");
+                    process::exit(1);
+                }
+            },
+        };
+
+        Migration { stage, matrix }
+    }
+
+    pub fn is_active(&self) -> bool {
+        // Any chance of leaving a deme
+        self.matrix
+            .iter()
+            .enumerate()
+            .any(|(i, row)| row.iter().enumerate().any(|(j, p)| i != j && *p > 0.0))
+    }
+
+    pub fn migrate(&self, groups: Vec<Vec<Fly>>, rng: &mut SimulationRng) -> Vec<Vec<Fly>> {
+        // Send each individual of each deme to a destination deme drawn
+        // from its row of the migration matrix
+        let mut migrated: Vec<Vec<Fly>> = vec![Vec::new(); groups.len()];
+
+        for (i, group) in groups.into_iter().enumerate() {
+            let destinations = WeightedIndex::new(&self.matrix[i]).unwrap_or_else(|_| {
+                eprintln!("Migration matrix row {} has no positive values", i + 1);
+                process::exit(1);
+            });
+
+            for fly in group {
+                migrated[destinations.sample(rng)].push(fly);
+            }
+        }
+
+        migrated
+    }
+}
+
+//// Functions
+fn island_matrix(rate: f64, number_demes: usize) -> Vec<Vec<f64>> {
+    // Migrants go to any other deme with equal probability
+    let mut matrix = vec![vec![0.0; number_demes]; number_demes];
+
+    for (i, row) in matrix.iter_mut().enumerate() {
+        for (j, p) in row.iter_mut().enumerate() {
+            *p = if i == j {
+                1.0 - rate
+            } else {
+                rate / (number_demes - 1) as f64
+            };
+        }
+    }
+
+    if number_demes == 1 {
+        matrix[0][0] = 1.0;
+    }
+
+    matrix
+}
+
+fn stepping_stone_matrix(rate: f64, number_demes: usize) -> Vec<Vec<f64>> {
+    // Migrants go to neighbouring demes along a line. Demes at both ends
+    // send all their migrants to their only neighbour
+    let mut matrix = vec![vec![0.0; number_demes]; number_demes];
+
+    for (i, row) in matrix.iter_mut().enumerate() {
+        let mut neighbours = Vec::new();
+
+        if i > 0 {
+            neighbours.push(i - 1);
+        }
+
+        if i + 1 < number_demes {
+            neighbours.push(i + 1);
+        }
+
+        if neighbours.is_empty() {
+            row[i] = 1.0;
+            continue;
+        }
+
+        row[i] = 1.0 - rate;
+
+        for n in neighbours.iter() {
+            row[*n] = rate / neighbours.len() as f64;
+        }
+    }
+
+    matrix
+}
+
+fn gaussian_matrix(sigma: f64, number_demes: usize) -> Vec<Vec<f64>> {
+    // Dispersal distance follows a normal distribution with standard
+    // deviation `sigma`, in positions. Individuals cannot leave the
+    // coastline so each row is normalized over existing positions
+    let mut matrix = vec![vec![0.0; number_demes]; number_demes];
+
+    for (i, row) in matrix.iter_mut().enumerate() {
+        if sigma <= 0.0 {
+            row[i] = 1.0;
+            continue;
+        }
+
+        for (j, p) in row.iter_mut().enumerate() {
+            let distance = i as f64 - j as f64;
+            *p = (-distance * distance / (2.0 * sigma * sigma)).exp();
+        }
+
+        let total: f64 = row.iter().sum();
+
+        for p in row.iter_mut() {
+            *p /= total;
+        }
+    }
+
+    matrix
+}
+
+fn read_matrix(path: &str, number_demes: usize) -> Vec<Vec<f64>> {
+    // Read one comma separated row per deme. Rows are normalized to 1.0
+    let content = fs::read_to_string(path).unwrap_or_else(|_| {
+        eprintln!("Cannot read migration matrix: {}", path);
+        process::exit(1);
+    });
+
+    let mut matrix = Vec::new();
+
+    for line in content.lines() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut row: Vec<f64> = line
+            .split(',')
+            .map(|v| {
+                v.trim()
+                    .parse::<f64>()
+                    .expect("Cannot parse migration value")
+            })
+            .collect();
+
+        if row.len() != number_demes {
+            eprintln!(
+                "Migration matrix rows need {} values: {}",
+                number_demes, line
+            );
+            process::exit(1);
+        }
+
+        let total: f64 = row.iter().sum();
+
+        for p in row.iter_mut() {
+            *p /= total;
+        }
+
+        matrix.push(row);
+    }
+
+    if matrix.len() != number_demes {
+        eprintln!(
+            "Migration matrix needs {} rows, got {}",
+            number_demes,
+            matrix.len()
+        );
+        process::exit(1);
+    }
+
+    matrix
+}
+
+pub fn read_demes(path: &str, base: &Parameters) -> Vec<Parameters> {
+    // Read one row of parameter overrides per deme. The header gives the
+    // parameter names and empty values keep the base parameter
+    let content = fs::read_to_string(path).unwrap_or_else(|_| {
+        eprintln!("Cannot read deme file: {}", path);
+        process::exit(1);
+    });
+
+    let mut lines = content
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'));
+
+    let header: Vec<&str> = match lines.next() {
+        Some(line) => line.split(',').map(|v| v.trim()).collect(),
+        None => {
+            eprintln!("Deme file is empty: {}", path);
+            process::exit(1);
+        }
+    };
+
+    let mut demes = Vec::new();
+
+    for line in lines {
+        let values: Vec<&str> = line.split(',').map(|v| v.trim()).collect();
+
+        if values.len() != header.len() {
+            eprintln!("Deme file rows need {} values: {}", header.len(), line);
+            process::exit(1);
+        }
+
+        let mut params = base.clone();
+
+        for (name, value) in header.iter().zip(values.iter()) {
+            if value.is_empty() {
+                continue;
+            }
+
+            let value = value.parse::<f64>().expect("Cannot parse deme value");
+
+            if let Err(e) = params.set(name, value) {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+
+        demes.push(params);
+    }
+
+    if demes.is_empty() {
+        eprintln!("Deme file has no demes: {}", path);
+        process::exit(1);
+    }
+
+    demes
+}
+
+pub fn coastline_demes(
+    base: &Parameters,
+    number_positions: usize,
+    environment_time_end: f64,
+) -> Vec<Parameters> {
+    // Demes along the coastline with environment time changing linearly
+    // from `environment_time` at the first position to
+    // `environment_time_end` at the last one
+    (0..number_positions)
+        .map(|i| {
+            let position = if number_positions > 1 {
+                i as f64 / (number_positions - 1) as f64
+            } else {
+                0.0
+            };
+            let mut params = base.clone();
+            params.environment_time =
+                base.environment_time + position * (environment_time_end - base.environment_time);
+            params
+        })
+        .collect()
+}
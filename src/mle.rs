@@ -0,0 +1,481 @@
+//// Maximum likelihood estimation
+// The `mle` subcommand fits parameters to observed genotype counts of
+// adults, given as `generation,AA,AB,BB` rows like for `abc` but only as
+// non-negative integer counts, proportions being rejected, under the
+// deterministic recursion of `Model::expected_adult_proportions`. The log
+// likelihood is the sum over observed generations of multinomial log
+// probabilities of the counts, without their constant term. It is
+// maximised within parameter ranges by the Nelder-Mead simplex method.
+// Bounds of 95% confidence intervals are where the profile log likelihood,
+// maximised over the other parameters, drops by half the 0.95 quantile of a
+// chi-square with one degree of freedom
+use clap::ArgMatches;
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::process;
+
+use crate::abc::{read_trajectory, Trajectory};
+use crate::parameters::Parameters;
+use crate::sensitivity::Range;
+use crate::simulation::Model;
+
+// Half the 0.95 quantile of a chi-square with one degree of freedom
+const PROFILE_DROP: f64 = 1.920_729_4;
+
+// Steps of bisection searching each confidence interval bound
+const BISECTION_STEPS: usize = 20;
+
+//// Structs
+pub struct Estimate {
+    pub name: String,
+    pub value: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+pub struct Likelihood {
+    base: Parameters,
+    ranges: Vec<Range>,
+    observed: Vec<Trajectory>,
+    max_iterations: usize,
+}
+
+impl Likelihood {
+    pub fn params_with(&self, values: &[f64]) -> Parameters {
+        let mut params = self.base.clone();
+
+        for (range, value) in self.ranges.iter().zip(values.iter()) {
+            if let Err(e) = params.set(&range.name, *value) {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+
+        params
+    }
+
+    pub fn log_likelihood(&self, values: &[f64]) -> f64 {
+        // Observed generations after the end of the recursion take its last
+        // proportions, or none at all
+        let expected = Model::new(&self.params_with(values)).expected_adult_proportions();
+        let last = expected.last().cloned().unwrap_or([0.0; 3]);
+        let mut log_likelihood = 0.0;
+
+        for observed in self.observed.iter() {
+            for (generation, counts) in observed.generations.iter().zip(observed.counts.iter()) {
+                let proportions = expected.get(*generation as usize).unwrap_or(&last);
+
+                for (count, proportion) in counts.iter().zip(proportions.iter()) {
+                    if *count > 0.0 {
+                        log_likelihood += count * proportion.max(f64::MIN_POSITIVE).ln();
+                    }
+                }
+            }
+        }
+
+        log_likelihood
+    }
+
+    fn maximise(&self, start: &[f64], fixed: Option<(usize, f64)>) -> (Vec<f64>, f64) {
+        // Maximise over the parameters that are not fixed, scaled to [0, 1]
+        // within their ranges
+        let free: Vec<usize> = (0..self.ranges.len())
+            .filter(|i| fixed.map(|(j, _)| j != *i).unwrap_or(true))
+            .collect();
+
+        let values_of = |scaled: &[f64]| {
+            let mut values = start.to_vec();
+
+            for (i, x) in free.iter().zip(scaled.iter()) {
+                let range = &self.ranges[*i];
+                values[*i] = range.low + x * (range.high - range.low);
+            }
+
+            if let Some((j, value)) = fixed {
+                values[j] = value;
+            }
+
+            values
+        };
+
+        let objective = |scaled: &[f64]| {
+            if scaled.iter().any(|x| *x < 0.0 || *x > 1.0) {
+                f64::NEG_INFINITY
+            } else {
+                self.log_likelihood(&values_of(scaled))
+            }
+        };
+
+        let scaled_start: Vec<f64> = free
+            .iter()
+            .map(|i| {
+                let range = &self.ranges[*i];
+
+                if range.high > range.low {
+                    (start[*i] - range.low) / (range.high - range.low)
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+
+        let (best, value) = nelder_mead(objective, &scaled_start, self.max_iterations);
+        (values_of(&best), value)
+    }
+
+    fn profile_bound(&self, estimate: &[f64], best: f64, i: usize, bound: f64) -> f64 {
+        // Value between the estimate and a range bound where the profile
+        // log likelihood reaches the drop, or the range bound itself
+        let threshold = best - PROFILE_DROP;
+        let profile = |value: f64| self.maximise(estimate, Some((i, value))).1;
+
+        if profile(bound) >= threshold {
+            return bound;
+        }
+
+        let mut inside = estimate[i];
+        let mut outside = bound;
+
+        for _ in 0..BISECTION_STEPS {
+            let middle = (inside + outside) / 2.0;
+
+            if profile(middle) >= threshold {
+                inside = middle;
+            } else {
+                outside = middle;
+            }
+        }
+
+        (inside + outside) / 2.0
+    }
+
+    pub fn fit(&self) -> (Vec<Estimate>, f64) {
+        // Start from the base values, kept within ranges
+        let start: Vec<f64> = self
+            .ranges
+            .iter()
+            .map(|r| self.base.get(&r.name).unwrap().clamp(r.low, r.high))
+            .collect();
+
+        let (values, best) = self.maximise(&start, None);
+
+        let estimates = self
+            .ranges
+            .iter()
+            .enumerate()
+            .map(|(i, range)| Estimate {
+                name: range.name.clone(),
+                value: values[i],
+                lower: self.profile_bound(&values, best, i, range.low),
+                upper: self.profile_bound(&values, best, i, range.high),
+            })
+            .collect();
+
+        (estimates, best)
+    }
+}
+
+//// Functions
+fn nelder_mead<F: Fn(&[f64]) -> f64>(
+    objective: F,
+    start: &[f64],
+    max_iterations: usize,
+) -> (Vec<f64>, f64) {
+    // Maximise `objective` with the Nelder-Mead simplex method, starting
+    // from a simplex of steps of 0.1 around `start`. Undefined values count
+    // as the worst possible, which also keeps vertices ordered
+    let objective = |x: &[f64]| {
+        let value = objective(x);
+
+        if value.is_nan() {
+            f64::NEG_INFINITY
+        } else {
+            value
+        }
+    };
+    let dimensions = start.len();
+
+    if dimensions == 0 {
+        return (Vec::new(), objective(start));
+    }
+
+    let mut simplex: Vec<Vec<f64>> = vec![start.to_vec()];
+
+    for i in 0..dimensions {
+        let mut vertex = start.to_vec();
+        vertex[i] += if vertex[i] + 0.1 <= 1.0 { 0.1 } else { -0.1 };
+        simplex.push(vertex);
+    }
+
+    let mut values: Vec<f64> = simplex.iter().map(|v| objective(v)).collect();
+
+    for _ in 0..max_iterations {
+        // Order vertices from best to worst
+        let mut order: Vec<usize> = (0..=dimensions).collect();
+        order.sort_by(|a, b| values[*b].partial_cmp(&values[*a]).unwrap());
+        simplex = order.iter().map(|i| simplex[*i].clone()).collect();
+        values = order.iter().map(|i| values[*i]).collect();
+
+        if (values[0] - values[dimensions]).abs() < 1e-9 {
+            break;
+        }
+
+        // Centroid of all but the worst vertex
+        let centroid: Vec<f64> = (0..dimensions)
+            .map(|j| simplex[..dimensions].iter().map(|v| v[j]).sum::<f64>() / dimensions as f64)
+            .collect();
+
+        let towards = |coefficient: f64| -> Vec<f64> {
+            centroid
+                .iter()
+                .zip(simplex[dimensions].iter())
+                .map(|(c, w)| c + coefficient * (w - c))
+                .collect()
+        };
+
+        let reflected = towards(-1.0);
+        let reflected_value = objective(&reflected);
+
+        if reflected_value > values[0] {
+            let expanded = towards(-2.0);
+            let expanded_value = objective(&expanded);
+
+            if expanded_value > reflected_value {
+                simplex[dimensions] = expanded;
+                values[dimensions] = expanded_value;
+            } else {
+                simplex[dimensions] = reflected;
+                values[dimensions] = reflected_value;
+            }
+        } else if reflected_value > values[dimensions - 1] {
+            simplex[dimensions] = reflected;
+            values[dimensions] = reflected_value;
+        } else {
+            let contracted = towards(0.5);
+            let contracted_value = objective(&contracted);
+
+            if contracted_value > values[dimensions] {
+                simplex[dimensions] = contracted;
+                values[dimensions] = contracted_value;
+            } else {
+                // Shrink towards the best vertex
+                for i in 1..=dimensions {
+                    simplex[i] = simplex[0]
+                        .iter()
+                        .zip(simplex[i].iter())
+                        .map(|(b, v)| b + 0.5 * (v - b))
+                        .collect();
+                    values[i] = objective(&simplex[i]);
+                }
+            }
+        }
+    }
+
+    let best = (0..=dimensions)
+        .max_by(|a, b| values[*a].partial_cmp(&values[*b]).unwrap())
+        .unwrap();
+
+    (simplex[best].clone(), values[best])
+}
+
+fn read_counts(path: &str) -> Trajectory {
+    // Observed trajectory whose genotypes are counts, as the multinomial
+    // likelihood needs, not proportions
+    let trajectory = read_trajectory(path);
+
+    for (generation, counts) in trajectory.generations.iter().zip(trajectory.counts.iter()) {
+        if counts.iter().any(|c| *c < 0.0 || c.fract() != 0.0) {
+            eprintln!(
+                "Observed genotypes of generation {} in {} are not counts: {:?}",
+                generation, path, counts
+            );
+            process::exit(1);
+        }
+    }
+
+    trajectory
+}
+
+pub fn main(base: &Parameters, matches: &ArgMatches) {
+    // Fit parameters to observed counts and write estimates with their
+    // confidence intervals
+    let ranges: Vec<Range> = matches
+        .values_of("parameter")
+        .map(|values| values.collect::<Vec<&str>>())
+        .unwrap_or_default()
+        .iter()
+        .map(|v| {
+            let range = v.parse::<Range>().unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                process::exit(1);
+            });
+
+            if let Err(e) = base.get(&range.name) {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+
+            range
+        })
+        .collect();
+
+    let observed: Vec<Trajectory> = matches
+        .values_of("observed")
+        .map(|values| values.map(read_counts).collect())
+        .unwrap_or_default();
+
+    if ranges.is_empty() || observed.is_empty() {
+        eprintln!("MLE needs at least one --parameter and one --observed trajectory");
+        process::exit(1);
+    }
+
+    let max_iterations = matches
+        .value_of("iterations")
+        .unwrap_or("1000")
+        .parse::<usize>()
+        .unwrap();
+
+    let likelihood = Likelihood {
+        base: base.clone(),
+        ranges,
+        observed,
+        max_iterations,
+    };
+
+    let (estimates, best) = likelihood.fit();
+
+    // Write estimates
+    let mut outfile: Box<dyn Write> = match matches.value_of("output") {
+        Some(path) => Box::new(File::create(path).expect("Cannot creat file")),
+        None => Box::new(io::stdout()),
+    };
+
+    writeln!(outfile, "parameter,estimate,lower95,upper95,logLikelihood")
+        .expect("Cannot write to file");
+
+    for estimate in estimates.iter() {
+        writeln!(
+            outfile,
+            "{},{},{},{},{}",
+            estimate.name, estimate.value, estimate.lower, estimate.upper, best
+        )
+        .expect("Cannot write to file");
+    }
+
+    // Expected trajectory under the estimates
+    if let Some(path) = matches.value_of("trajectory") {
+        let values: Vec<f64> = estimates.iter().map(|e| e.value).collect();
+        let expected = Model::new(&likelihood.params_with(&values)).expected_adult_proportions();
+        let mut trajectory_file = File::create(path).expect("Cannot creat file");
+
+        writeln!(trajectory_file, "Generation,adultAA,adultAB,adultBB")
+            .expect("Cannot write to file");
+
+        for (generation, proportions) in expected.iter().enumerate() {
+            writeln!(
+                trajectory_file,
+                "{},{},{},{}",
+                generation, proportions[0], proportions[1], proportions[2]
+            )
+            .expect("Cannot write to file");
+        }
+    }
+}
+
+//// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nelder_mead_finds_the_maximum_of_a_quadratic() {
+        let quadratic = |x: &[f64]| -(x[0] - 0.3).powi(2) - 2.0 * (x[1] - 0.7).powi(2) + 1.0;
+        let (best, value) = nelder_mead(quadratic, &[0.5, 0.5], 1000);
+
+        assert!((best[0] - 0.3).abs() < 1e-3, "{:?}", best);
+        assert!((best[1] - 0.7).abs() < 1e-3, "{:?}", best);
+        assert!((value - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn nelder_mead_avoids_undefined_values() {
+        // Undefined past 0.6, with the maximum of the quadratic beyond it
+        let objective = |x: &[f64]| {
+            if x[0] > 0.6 {
+                f64::NAN
+            } else {
+                -(x[0] - 0.8).powi(2)
+            }
+        };
+        let (best, value) = nelder_mead(objective, &[0.2], 1000);
+
+        assert!(best[0] <= 0.6 && best[0] > 0.55, "{:?}", best);
+        assert!(value.is_finite());
+    }
+
+    #[test]
+    fn fit_recovers_known_parameters() {
+        // Counts of 2000 adults every other generation, exactly as expected
+        // under known parameters, give estimates close to them within
+        // their confidence intervals
+        let mut base =
+            Parameters::from_matches(&clap::App::new("test").get_matches_from(vec!["test"]));
+        base.number_generations = 20;
+        base.proportion_aa = 0.2;
+        base.proportion_bb = 0.3;
+
+        let truth = [0.4, 0.6];
+        let mut params = base.clone();
+        params.set("male_success_bb", truth[0]).unwrap();
+        params.set("survival_males_bb", truth[1]).unwrap();
+        let expected = Model::new(&params).expected_adult_proportions();
+
+        let mut observed = Trajectory {
+            generations: Vec::new(),
+            counts: Vec::new(),
+            proportions: Vec::new(),
+        };
+
+        for (generation, proportions) in expected.iter().enumerate().step_by(2) {
+            let mut counts = [0.0; 3];
+
+            for (count, proportion) in counts.iter_mut().zip(proportions.iter()) {
+                *count = (proportion * 2000.0).round();
+            }
+
+            observed.generations.push(generation as u32);
+            observed.counts.push(counts);
+            observed.proportions.push(*proportions);
+        }
+
+        let likelihood = Likelihood {
+            base,
+            ranges: vec![
+                "male_success_bb=0.01:1.0".parse::<Range>().unwrap(),
+                "survival_males_bb=0.1:1.0".parse::<Range>().unwrap(),
+            ],
+            observed: vec![observed],
+            max_iterations: 1000,
+        };
+
+        let (estimates, _) = likelihood.fit();
+
+        for (estimate, value) in estimates.iter().zip(truth.iter()) {
+            assert!(
+                (estimate.value - value).abs() < 0.05,
+                "{} estimated at {}",
+                estimate.name,
+                estimate.value
+            );
+            assert!(
+                estimate.lower <= *value && *value <= estimate.upper,
+                "{} not in [{}, {}]",
+                estimate.name,
+                estimate.lower,
+                estimate.upper
+            );
+            assert!(estimate.lower < estimate.value && estimate.value < estimate.upper);
+        }
+    }
+}
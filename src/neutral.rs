@@ -0,0 +1,329 @@
+//// Neutral sites linked to the inversion
+// Each chromosome carries up to 64 biallelic neutral sites stored as the
+// bits of an integer. Sites are evenly spaced along a chromosome of length
+// 1.0 and those between `inversion_start` and `inversion_end` are inside
+// the inversion. Crossovers happen anywhere in AA and BB homokaryotypes.
+// In AB heterokaryotypes, crossovers inside the inversion are suppressed
+// and sites inside it are only exchanged by gene flux
+use rand::distributions::{Distribution, Poisson};
+use rand::Rng;
+
+use std::process;
+
+use crate::parameters::Parameters;
+use crate::random::SimulationRng;
+use crate::{allele_from_parent, Fly, Genotype, Inheritance, Sex};
+
+pub const MAX_SITES: usize = 64;
+
+//// Structs
+pub struct Linkage {
+    pub number_sites: usize,
+    positions: Vec<f64>,
+    inside: u64,
+    inversion_start: f64,
+    inversion_end: f64,
+    recombination_rate: f64,
+    gene_flux_rate: f64,
+    mutation_rate: f64,
+    x_linked: bool,
+    drive_females: f64,
+    drive_males: f64,
+}
+
+pub struct RegionStats {
+    pub region: &'static str,
+    pub chromosomes_a: usize,
+    pub chromosomes_b: usize,
+    pub pi_a: f64,
+    pub pi_b: f64,
+    pub dxy: f64,
+    pub fst: f64,
+}
+
+impl Linkage {
+    pub fn new(params: &Parameters) -> Linkage {
+        let number_sites = params.neutral_sites;
+
+        if number_sites > MAX_SITES {
+            eprintln!("At most {} neutral sites can be simulated", MAX_SITES);
+            process::exit(1);
+        }
+
+        // Sites at the middle of equal chromosome segments
+        let positions: Vec<f64> = (0..number_sites)
+            .map(|i| (i as f64 + 0.5) / number_sites as f64)
+            .collect();
+
+        let mut inside = 0;
+
+        for (i, position) in positions.iter().enumerate() {
+            if *position >= params.inversion_start && *position < params.inversion_end {
+                inside |= 1 << i;
+            }
+        }
+
+        Linkage {
+            number_sites,
+            positions,
+            inside,
+            inversion_start: params.inversion_start,
+            inversion_end: params.inversion_end,
+            recombination_rate: params.recombination_rate,
+            gene_flux_rate: params.gene_flux_rate,
+            mutation_rate: params.neutral_mutation_rate,
+            x_linked: params.inheritance == Inheritance::XLinked,
+            drive_females: params.drive_k_females,
+            drive_males: params.drive_k_males,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.number_sites > 0
+    }
+
+    pub fn positions(&self) -> &[f64] {
+        &self.positions
+    }
+
+    pub fn hemizygous(&self, fly: &Fly) -> bool {
+        // Males carry a single chromosome under X linkage
+        self.x_linked && fly.sex == Sex::Male
+    }
+
+    fn transmission_b(&self, parent: &Fly) -> f64 {
+        // Probability that an AB parent transmits its B chromosome
+        match parent.sex {
+            Sex::Female => self.drive_females,
+            Sex::Male => self.drive_males,
+        }
+    }
+
+    fn all_sites(&self) -> u64 {
+        if self.number_sites == MAX_SITES {
+            u64::MAX
+        } else {
+            (1 << self.number_sites) - 1
+        }
+    }
+
+    pub fn random_haplotype(&self, rng: &mut SimulationRng) -> u64 {
+        // Starting haplotypes have each derived allele at frequency 0.5
+        rng.gen::<u64>() & self.all_sites()
+    }
+
+    pub fn gamete(&self, parent: &Fly, rng: &mut SimulationRng) -> (usize, char, u64) {
+        // Return the chromosome transmitted by a parent, with its
+        // arrangement and neutral sites after recombination
+        let arrangements = chromosome_arrangements(parent.genotype);
+
+        if !self.is_active() {
+            let allele = allele_from_parent(*parent, self.transmission_b(parent), rng);
+            let chromosome = match parent.genotype {
+                Genotype::AB if allele == 'B' => 1,
+                Genotype::AB => 0,
+                _ => rng.gen_range(0, 2),
+            };
+
+            return (chromosome, allele, 0);
+        }
+
+        let heterokaryotype = parent.genotype == Genotype::AB;
+
+        // Chromosome 1 carries B in AB parents, subject to meiotic drive
+        let probability_second = if heterokaryotype {
+            self.transmission_b(parent)
+        } else {
+            0.5
+        };
+        let first: usize = if rng.gen::<f64>() < probability_second {
+            1
+        } else {
+            0
+        };
+        let other = 1 - first;
+
+        // Crossovers along the chromosome, except inside the inversion of
+        // heterokaryotypes. Sites after an odd number of crossovers come
+        // from the other chromosome
+        let number_crossovers = if self.recombination_rate > 0.0 {
+            Poisson::new(self.recombination_rate).sample(rng)
+        } else {
+            0
+        };
+
+        let mut crossovers: Vec<f64> = (0..number_crossovers)
+            .map(|_| rng.gen::<f64>())
+            .filter(|c| !(heterokaryotype && *c >= self.inversion_start && *c < self.inversion_end))
+            .collect();
+        crossovers.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let mut from_other: u64 = 0;
+
+        for (i, position) in self.positions.iter().enumerate() {
+            let switches = crossovers.iter().filter(|c| *c < position).count();
+
+            if switches % 2 == 1 {
+                from_other |= 1 << i;
+            }
+        }
+
+        // Gene flux moves single sites inside the inversion between
+        // arrangements
+        if heterokaryotype && self.gene_flux_rate > 0.0 {
+            for i in 0..self.number_sites {
+                if self.inside & (1 << i) != 0 && rng.gen::<f64>() < self.gene_flux_rate {
+                    from_other ^= 1 << i;
+                }
+            }
+        }
+
+        let mut haplotype =
+            (parent.haplotypes[first] & !from_other) | (parent.haplotypes[other] & from_other);
+
+        // Neutral mutations flip sites
+        if self.mutation_rate > 0.0 {
+            let expected = self.mutation_rate * self.number_sites as f64;
+            let number_mutations = Poisson::new(expected).sample(rng);
+
+            for _ in 0..number_mutations {
+                haplotype ^= 1 << rng.gen_range(0, self.number_sites);
+            }
+        }
+
+        (first, arrangements[first], haplotype)
+    }
+
+    pub fn statistics(&self, flies: &[Fly]) -> Vec<RegionStats> {
+        // Diversity within each arrangement and divergence between them,
+        // inside and outside the inversion
+        let mut chromosomes_a: Vec<u64> = Vec::new();
+        let mut chromosomes_b: Vec<u64> = Vec::new();
+
+        for fly in flies.iter() {
+            let arrangements = chromosome_arrangements(fly.genotype);
+            let number_chromosomes = if self.hemizygous(fly) { 1 } else { 2 };
+
+            for (arrangement, haplotype) in arrangements
+                .iter()
+                .zip(fly.haplotypes.iter())
+                .take(number_chromosomes)
+            {
+                if *arrangement == 'A' {
+                    chromosomes_a.push(*haplotype);
+                } else {
+                    chromosomes_b.push(*haplotype);
+                }
+            }
+        }
+
+        let outside = self.all_sites() & !self.inside;
+
+        vec![
+            self.region_statistics("inside", self.inside, &chromosomes_a, &chromosomes_b),
+            self.region_statistics("outside", outside, &chromosomes_a, &chromosomes_b),
+        ]
+    }
+
+    pub fn site_frequencies(&self, flies: &[Fly]) -> Vec<f64> {
+        // Derived allele frequency of each site over all chromosomes
+        let mut chromosomes: Vec<u64> = Vec::new();
+
+        for fly in flies.iter() {
+            let number_chromosomes = if self.hemizygous(fly) { 1 } else { 2 };
+            chromosomes.extend(fly.haplotypes.iter().take(number_chromosomes));
+        }
+
+        (0..self.number_sites)
+            .map(|i| derived_frequency(&chromosomes, i))
+            .collect()
+    }
+
+    fn region_statistics(
+        &self,
+        region: &'static str,
+        mask: u64,
+        chromosomes_a: &[u64],
+        chromosomes_b: &[u64],
+    ) -> RegionStats {
+        let mut pi_a = 0.0;
+        let mut pi_b = 0.0;
+        let mut dxy = 0.0;
+        let mut number_sites = 0;
+
+        for i in 0..self.number_sites {
+            if mask & (1 << i) == 0 {
+                continue;
+            }
+
+            let p_a = derived_frequency(chromosomes_a, i);
+            let p_b = derived_frequency(chromosomes_b, i);
+
+            pi_a += unbiased_diversity(p_a, chromosomes_a.len());
+            pi_b += unbiased_diversity(p_b, chromosomes_b.len());
+            dxy += p_a * (1.0 - p_b) + p_b * (1.0 - p_a);
+            number_sites += 1;
+        }
+
+        if number_sites > 0 {
+            pi_a /= f64::from(number_sites);
+            pi_b /= f64::from(number_sites);
+            dxy /= f64::from(number_sites);
+        }
+
+        // Hudson's FST between arrangements
+        let fst = 1.0 - (pi_a + pi_b) / 2.0 / dxy;
+
+        RegionStats {
+            region,
+            chromosomes_a: chromosomes_a.len(),
+            chromosomes_b: chromosomes_b.len(),
+            pi_a,
+            pi_b,
+            dxy,
+            fst,
+        }
+    }
+}
+
+//// Functions
+pub fn chromosome_arrangements(genotype: Genotype) -> [char; 2] {
+    // Arrangement of each chromosome. AB individuals carry the A
+    // arrangement on their first chromosome
+    match genotype {
+        Genotype::AA => ['A', 'A'],
+        Genotype::AB => ['A', 'B'],
+        Genotype::BB => ['B', 'B'],
+    }
+}
+
+pub fn offspring_chromosomes<T: Copy>(
+    female_allele: char,
+    female_chromosome: T,
+    male_chromosome: T,
+    genotype: Genotype,
+) -> [T; 2] {
+    // Order the chromosomes of an egg so the A arrangement comes first
+    if genotype == Genotype::AB && female_allele == 'B' {
+        [male_chromosome, female_chromosome]
+    } else {
+        [female_chromosome, male_chromosome]
+    }
+}
+
+fn derived_frequency(chromosomes: &[u64], site: usize) -> f64 {
+    if chromosomes.is_empty() {
+        return f64::NAN;
+    }
+
+    let count = chromosomes.iter().filter(|h| *h & (1 << site) != 0).count();
+    count as f64 / chromosomes.len() as f64
+}
+
+fn unbiased_diversity(p: f64, n: usize) -> f64 {
+    if n < 2 {
+        return f64::NAN;
+    }
+
+    2.0 * p * (1.0 - p) * n as f64 / (n - 1) as f64
+}
@@ -0,0 +1,1034 @@
+//// Simulation parameters
+// Values given on the command line, with their defaults. Parameter names
+// are the underscore versions of the long options and can also be changed
+// by name, for example to override them in one deme
+use clap::ArgMatches;
+use std::process;
+
+use crate::body_size::SizeModel;
+use crate::demography::{self, CapacityModel};
+use crate::freq_dep::FreqDepModel;
+use crate::loci::LocusCombination;
+use crate::temperature::{DevelopmentModel, TemperatureModel};
+use crate::{DensityModel, Expression, Inheritance, MatesDistribution, SpermPrecedence};
+
+//// Structs
+#[derive(Clone, Debug)]
+pub struct Parameters {
+    pub experiment_name: String,
+    pub number_generations: u32,
+    pub proportion_females: f64,
+    pub number_eggs_per_generation: usize,
+    pub number_eggs_per_female: f64,
+    pub proportion_aa: f64,
+    pub proportion_bb: f64,
+    pub survival_global: f64,
+    pub survival_females_aa: f64,
+    pub survival_females_ab: f64,
+    pub survival_females_bb: f64,
+    pub survival_males_aa: f64,
+    pub survival_males_ab: f64,
+    pub survival_males_bb: f64,
+    pub female_eggs_aa: f64,
+    pub female_eggs_ab: f64,
+    pub female_eggs_bb: f64,
+    pub male_success_aa: f64,
+    pub male_success_ab: f64,
+    pub male_success_bb: f64,
+    pub male_freq_dep_coef: f64,
+    pub male_freq_dep_model: FreqDepModel,
+    pub male_freq_dep_exponent: f64,
+    pub male_freq_dep_table: Option<String>,
+    pub egg_density_model: DensityModel,
+    pub competition_aa: f64,
+    pub competition_ab: f64,
+    pub competition_bb: f64,
+    pub carrying_capacity_model: CapacityModel,
+    pub carrying_capacity_amplitude: f64,
+    pub carrying_capacity_period: f64,
+    pub carrying_capacity_schedule: Vec<(u32, f64)>,
+    pub report_population_size: bool,
+    pub female_freq_dep_coef: f64,
+    pub female_preference: [[f64; 3]; 3],
+    pub assortative_mating: f64,
+    pub number_mates: f64,
+    pub mates_distribution: MatesDistribution,
+    pub sperm_precedence: SpermPrecedence,
+    pub sperm_precedence_strength: f64,
+    pub female_maturation_days: f64,
+    pub male_maturation_days_aa: f64,
+    pub male_maturation_days_ab: f64,
+    pub male_maturation_days_bb: f64,
+    pub maturation_cv: f64,
+    pub environment_time: f64,
+    pub environment_time_variation: f64,
+    pub mutation_rate_a_to_b: f64,
+    pub mutation_rate_b_to_a: f64,
+    pub introduction_generation: Option<u32>,
+    pub introduction_allele: char,
+    pub neutral_sites: usize,
+    pub inversion_start: f64,
+    pub inversion_end: f64,
+    pub recombination_rate: f64,
+    pub gene_flux_rate: f64,
+    pub neutral_mutation_rate: f64,
+    pub loci_file: Option<String>,
+    pub epistasis_file: Option<String>,
+    pub locus_combination: LocusCombination,
+    pub inheritance: Inheritance,
+    pub survival_expression: Expression,
+    pub maturation_expression: Expression,
+    pub drive_k_females: f64,
+    pub drive_k_males: f64,
+    pub maternal_survival_aa: f64,
+    pub maternal_survival_ab: f64,
+    pub maternal_survival_bb: f64,
+    pub maternal_maturation_aa: f64,
+    pub maternal_maturation_ab: f64,
+    pub maternal_maturation_bb: f64,
+    pub maternal_maturation_slope: f64,
+    pub carryover_survival: f64,
+    pub carryover_maturation: f64,
+    pub size_model: SizeModel,
+    pub male_size_aa: f64,
+    pub male_size_ab: f64,
+    pub male_size_bb: f64,
+    pub male_size_sd: f64,
+    pub size_maturation_intercept: f64,
+    pub size_maturation_slope: f64,
+    pub size_success_exponent: f64,
+    pub development_model: DevelopmentModel,
+    pub temperature_model: TemperatureModel,
+    pub temperature: f64,
+    pub temperature_sd: f64,
+    pub temperature_schedule: Vec<(u32, f64)>,
+    pub reference_temperature: f64,
+    pub development_threshold_aa: f64,
+    pub development_threshold_ab: f64,
+    pub development_threshold_bb: f64,
+    pub effective_size: bool,
+    pub report_hwe: bool,
+    pub pedigree: bool,
+    pub tree_sequence: bool,
+    pub tree_sequence_samples: usize,
+    pub vcf_samples: usize,
+    pub vcf_generations: Vec<u32>,
+    pub seed: Option<u64>,
+    pub checkpoint_interval: u32,
+    pub resume: bool,
+    pub stop_when_fixated: bool,
+    pub quiet: bool,
+}
+
+impl Parameters {
+    pub fn from_matches(matches: &ArgMatches) -> Parameters {
+        let experiment_name = matches
+            .value_of("experiment_name")
+            .unwrap_or("unnamed_experiment")
+            .to_string();
+
+        let number_generations = matches
+            .value_of("number_generations")
+            .unwrap_or("5")
+            .parse::<u32>()
+            .unwrap();
+
+        let proportion_females = matches
+            .value_of("proportion_females")
+            .unwrap_or("0.5")
+            .parse::<f64>()
+            .unwrap();
+
+        let number_eggs_per_generation = matches
+            .value_of("number_eggs_per_generation")
+            .unwrap_or("1000")
+            .parse::<usize>()
+            .unwrap();
+
+        let number_eggs_per_female = matches
+            .value_of("number_eggs_per_female")
+            .unwrap_or("50")
+            .parse::<f64>()
+            .unwrap();
+
+        let proportion_aa = matches
+            .value_of("proportion_aa")
+            .unwrap_or("0.07")
+            .parse::<f64>()
+            .unwrap();
+
+        let proportion_bb = matches
+            .value_of("proportion_bb")
+            .unwrap_or("0.44")
+            .parse::<f64>()
+            .unwrap();
+
+        let survival_global = matches
+            .value_of("survival_global")
+            .unwrap_or("0.3")
+            .parse::<f64>()
+            .unwrap();
+
+        let survival_females_aa = matches
+            .value_of("survival_females_aa")
+            .unwrap_or("0.71")
+            .parse::<f64>()
+            .unwrap();
+
+        let survival_females_ab = matches
+            .value_of("survival_females_ab")
+            .unwrap_or("0.9")
+            .parse::<f64>()
+            .unwrap();
+
+        let survival_females_bb = matches
+            .value_of("survival_females_bb")
+            .unwrap_or("1.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let survival_males_aa = matches
+            .value_of("survival_males_aa")
+            .unwrap_or("0.81")
+            .parse::<f64>()
+            .unwrap();
+
+        let survival_males_ab = matches
+            .value_of("survival_males_ab")
+            .unwrap_or("1.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let survival_males_bb = matches
+            .value_of("survival_males_bb")
+            .unwrap_or("0.88")
+            .parse::<f64>()
+            .unwrap();
+
+        let female_eggs_aa = matches
+            .value_of("female_eggs_aa")
+            .unwrap_or("1.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let female_eggs_ab = matches
+            .value_of("female_eggs_ab")
+            .unwrap_or("0.97")
+            .parse::<f64>()
+            .unwrap();
+
+        let female_eggs_bb = matches
+            .value_of("female_eggs_bb")
+            .unwrap_or("0.87")
+            .parse::<f64>()
+            .unwrap();
+
+        let male_success_aa = matches
+            .value_of("male_success_aa")
+            .unwrap_or("1.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let male_success_ab = matches
+            .value_of("male_success_ab")
+            .unwrap_or("0.55")
+            .parse::<f64>()
+            .unwrap();
+
+        let male_success_bb = matches
+            .value_of("male_success_bb")
+            .unwrap_or("0.1")
+            .parse::<f64>()
+            .unwrap();
+
+        let male_freq_dep_coef = matches
+            .value_of("male_freq_dep_coef")
+            .unwrap_or("0.1")
+            .parse::<f64>()
+            .unwrap();
+
+        let male_freq_dep_model = matches
+            .value_of("male_freq_dep_model")
+            .unwrap_or("linear")
+            .parse::<FreqDepModel>()
+            .unwrap();
+
+        let male_freq_dep_exponent = matches
+            .value_of("male_freq_dep_exponent")
+            .unwrap_or("1.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let male_freq_dep_table = matches.value_of("male_freq_dep_table").map(String::from);
+
+        let egg_density_model = matches
+            .value_of("egg_density_model")
+            .unwrap_or("none")
+            .parse::<DensityModel>()
+            .unwrap();
+
+        let competition_aa = matches
+            .value_of("competition_aa")
+            .unwrap_or("1.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let competition_ab = matches
+            .value_of("competition_ab")
+            .unwrap_or("1.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let competition_bb = matches
+            .value_of("competition_bb")
+            .unwrap_or("1.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let carrying_capacity_model = matches
+            .value_of("carrying_capacity_model")
+            .unwrap_or("constant")
+            .parse::<CapacityModel>()
+            .unwrap();
+
+        let carrying_capacity_amplitude = matches
+            .value_of("carrying_capacity_amplitude")
+            .unwrap_or("0.5")
+            .parse::<f64>()
+            .unwrap();
+
+        let carrying_capacity_period = matches
+            .value_of("carrying_capacity_period")
+            .unwrap_or("20")
+            .parse::<f64>()
+            .unwrap();
+
+        let carrying_capacity_schedule = match matches.value_of("carrying_capacity_schedule") {
+            Some(path) => demography::read_schedule(path, "carrying capacity"),
+            None if carrying_capacity_model == CapacityModel::Schedule => {
+                eprintln!("Carrying capacity model 'schedule' needs --carrying-capacity-schedule");
+                process::exit(1);
+            }
+            None => Vec::new(),
+        };
+
+        let report_population_size = match matches.occurrences_of("report_population_size") {
+            0 => false,
+            1 => true,
+            _ => false,
+        };
+
+        let female_freq_dep_coef = matches
+            .value_of("female_freq_dep_coef")
+            .unwrap_or("0.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let female_preference = parse_preference_matrix(
+            matches
+                .value_of("female_preference")
+                .unwrap_or("1,1,1,1,1,1,1,1,1"),
+        );
+
+        let assortative_mating = matches
+            .value_of("assortative_mating")
+            .unwrap_or("0.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let number_mates = matches
+            .value_of("number_mates")
+            .unwrap_or("1")
+            .parse::<f64>()
+            .unwrap();
+
+        let mates_distribution = matches
+            .value_of("mates_distribution")
+            .unwrap_or("fixed")
+            .parse::<MatesDistribution>()
+            .unwrap();
+
+        let sperm_precedence = matches
+            .value_of("sperm_precedence")
+            .unwrap_or("last")
+            .parse::<SpermPrecedence>()
+            .unwrap();
+
+        let sperm_precedence_strength = matches
+            .value_of("sperm_precedence_strength")
+            .unwrap_or("1.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let female_maturation_days = matches
+            .value_of("female_maturation_days")
+            .unwrap_or("8.8")
+            .parse::<f64>()
+            .unwrap();
+
+        let male_maturation_days_aa = matches
+            .value_of("male_maturation_days_aa")
+            .unwrap_or("12.8")
+            .parse::<f64>()
+            .unwrap();
+
+        let male_maturation_days_ab = matches
+            .value_of("male_maturation_days_ab")
+            .unwrap_or("10.3")
+            .parse::<f64>()
+            .unwrap();
+
+        let male_maturation_days_bb = matches
+            .value_of("male_maturation_days_bb")
+            .unwrap_or("8.7")
+            .parse::<f64>()
+            .unwrap();
+
+        let maturation_cv = matches
+            .value_of("maturation_cv")
+            .unwrap_or("0.5")
+            .parse::<f64>()
+            .unwrap();
+
+        let environment_time = matches
+            .value_of("environment_time")
+            .unwrap_or("10.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let environment_time_variation = matches
+            .value_of("environment_time_variation")
+            .unwrap_or("1.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let mutation_rate_a_to_b = matches
+            .value_of("mutation_rate_a_to_b")
+            .unwrap_or("0.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let mutation_rate_b_to_a = matches
+            .value_of("mutation_rate_b_to_a")
+            .unwrap_or("0.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let introduction_generation = matches
+            .value_of("introduction_generation")
+            .map(|g| g.parse::<u32>().unwrap());
+
+        let introduction_allele = matches
+            .value_of("introduction_allele")
+            .unwrap_or("B")
+            .chars()
+            .next()
+            .unwrap();
+
+        let neutral_sites = matches
+            .value_of("neutral_sites")
+            .unwrap_or("0")
+            .parse::<usize>()
+            .unwrap();
+
+        let inversion_start = matches
+            .value_of("inversion_start")
+            .unwrap_or("0.25")
+            .parse::<f64>()
+            .unwrap();
+
+        let inversion_end = matches
+            .value_of("inversion_end")
+            .unwrap_or("0.75")
+            .parse::<f64>()
+            .unwrap();
+
+        let recombination_rate = matches
+            .value_of("recombination_rate")
+            .unwrap_or("1.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let gene_flux_rate = matches
+            .value_of("gene_flux_rate")
+            .unwrap_or("0.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let neutral_mutation_rate = matches
+            .value_of("neutral_mutation_rate")
+            .unwrap_or("0.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let loci_file = matches.value_of("loci_file").map(String::from);
+
+        let epistasis_file = matches.value_of("epistasis_file").map(String::from);
+
+        let locus_combination = matches
+            .value_of("locus_combination")
+            .unwrap_or("multiplicative")
+            .parse::<LocusCombination>()
+            .unwrap();
+
+        let inheritance = matches
+            .value_of("inheritance")
+            .unwrap_or("autosomal")
+            .parse::<Inheritance>()
+            .unwrap();
+
+        let survival_expression = matches
+            .value_of("survival_expression")
+            .unwrap_or("both")
+            .parse::<Expression>()
+            .unwrap();
+
+        let maturation_expression = matches
+            .value_of("maturation_expression")
+            .unwrap_or("both")
+            .parse::<Expression>()
+            .unwrap();
+
+        let drive_k_females = matches
+            .value_of("drive_k_females")
+            .unwrap_or("0.5")
+            .parse::<f64>()
+            .unwrap();
+
+        let drive_k_males = matches
+            .value_of("drive_k_males")
+            .unwrap_or("0.5")
+            .parse::<f64>()
+            .unwrap();
+
+        let maternal_survival_aa = matches
+            .value_of("maternal_survival_aa")
+            .unwrap_or("1.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let maternal_survival_ab = matches
+            .value_of("maternal_survival_ab")
+            .unwrap_or("1.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let maternal_survival_bb = matches
+            .value_of("maternal_survival_bb")
+            .unwrap_or("1.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let maternal_maturation_aa = matches
+            .value_of("maternal_maturation_aa")
+            .unwrap_or("0.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let maternal_maturation_ab = matches
+            .value_of("maternal_maturation_ab")
+            .unwrap_or("0.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let maternal_maturation_bb = matches
+            .value_of("maternal_maturation_bb")
+            .unwrap_or("0.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let maternal_maturation_slope = matches
+            .value_of("maternal_maturation_slope")
+            .unwrap_or("0.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let carryover_survival = matches
+            .value_of("carryover_survival")
+            .unwrap_or("0.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let carryover_maturation = matches
+            .value_of("carryover_maturation")
+            .unwrap_or("0.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let size_model = matches
+            .value_of("size_model")
+            .unwrap_or("genotype")
+            .parse::<SizeModel>()
+            .unwrap();
+
+        let male_size_aa = matches
+            .value_of("male_size_aa")
+            .unwrap_or("1.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let male_size_ab = matches
+            .value_of("male_size_ab")
+            .unwrap_or("0.8")
+            .parse::<f64>()
+            .unwrap();
+
+        let male_size_bb = matches
+            .value_of("male_size_bb")
+            .unwrap_or("0.6")
+            .parse::<f64>()
+            .unwrap();
+
+        let male_size_sd = matches
+            .value_of("male_size_sd")
+            .unwrap_or("0.05")
+            .parse::<f64>()
+            .unwrap();
+
+        let size_maturation_intercept = matches
+            .value_of("size_maturation_intercept")
+            .unwrap_or("2.55")
+            .parse::<f64>()
+            .unwrap();
+
+        let size_maturation_slope = matches
+            .value_of("size_maturation_slope")
+            .unwrap_or("10.25")
+            .parse::<f64>()
+            .unwrap();
+
+        let size_success_exponent = matches
+            .value_of("size_success_exponent")
+            .unwrap_or("4.5")
+            .parse::<f64>()
+            .unwrap();
+
+        let development_model = matches
+            .value_of("development_model")
+            .unwrap_or("days")
+            .parse::<DevelopmentModel>()
+            .unwrap();
+
+        let temperature_model = matches
+            .value_of("temperature_model")
+            .unwrap_or("constant")
+            .parse::<TemperatureModel>()
+            .unwrap();
+
+        let temperature = matches
+            .value_of("temperature")
+            .unwrap_or("20.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let temperature_sd = matches
+            .value_of("temperature_sd")
+            .unwrap_or("0.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let temperature_schedule = match matches.value_of("temperature_schedule") {
+            Some(path) => demography::read_schedule(path, "temperature"),
+            None if temperature_model == TemperatureModel::Schedule => {
+                eprintln!("Temperature model 'schedule' needs --temperature-schedule");
+                process::exit(1);
+            }
+            None => Vec::new(),
+        };
+
+        let reference_temperature = matches
+            .value_of("reference_temperature")
+            .unwrap_or("20.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let development_threshold_aa = matches
+            .value_of("development_threshold_aa")
+            .unwrap_or("10.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let development_threshold_ab = matches
+            .value_of("development_threshold_ab")
+            .unwrap_or("10.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let development_threshold_bb = matches
+            .value_of("development_threshold_bb")
+            .unwrap_or("10.0")
+            .parse::<f64>()
+            .unwrap();
+
+        let effective_size = match matches.occurrences_of("effective_size") {
+            0 => false,
+            1 => true,
+            _ => false,
+        };
+
+        let report_hwe = match matches.occurrences_of("report_hwe") {
+            0 => false,
+            1 => true,
+            _ => false,
+        };
+
+        let pedigree = match matches.occurrences_of("pedigree") {
+            0 => false,
+            1 => true,
+            _ => false,
+        };
+
+        let tree_sequence = match matches.occurrences_of("tree_sequence") {
+            0 => false,
+            1 => true,
+            _ => false,
+        };
+
+        let tree_sequence_samples = matches
+            .value_of("tree_sequence_samples")
+            .unwrap_or("0")
+            .parse::<usize>()
+            .unwrap();
+
+        let vcf_samples = matches
+            .value_of("vcf_samples")
+            .unwrap_or("0")
+            .parse::<usize>()
+            .unwrap();
+
+        let vcf_generations = matches
+            .value_of("vcf_generations")
+            .map(|v| {
+                v.split(',')
+                    .map(|g| {
+                        g.trim()
+                            .parse::<u32>()
+                            .expect("Cannot parse VCF generation")
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let seed = matches.value_of("seed").map(|s| s.parse::<u64>().unwrap());
+
+        let checkpoint_interval = matches
+            .value_of("checkpoint_interval")
+            .unwrap_or("0")
+            .parse::<u32>()
+            .unwrap();
+
+        let resume = match matches.occurrences_of("resume") {
+            0 => false,
+            1 => true,
+            _ => false,
+        };
+
+        let stop_when_fixated = match matches.occurrences_of("stop_when_fixated") {
+            0 => false,
+            1 => true,
+            _ => false,
+        };
+
+        let quiet = match matches.occurrences_of("quiet") {
+            0 => false,
+            1 => true,
+            _ => false,
+        };
+
+        Parameters {
+            experiment_name,
+            number_generations,
+            proportion_females,
+            number_eggs_per_generation,
+            number_eggs_per_female,
+            proportion_aa,
+            proportion_bb,
+            survival_global,
+            survival_females_aa,
+            survival_females_ab,
+            survival_females_bb,
+            survival_males_aa,
+            survival_males_ab,
+            survival_males_bb,
+            female_eggs_aa,
+            female_eggs_ab,
+            female_eggs_bb,
+            male_success_aa,
+            male_success_ab,
+            male_success_bb,
+            male_freq_dep_coef,
+            male_freq_dep_model,
+            male_freq_dep_exponent,
+            male_freq_dep_table,
+            egg_density_model,
+            competition_aa,
+            competition_ab,
+            competition_bb,
+            carrying_capacity_model,
+            carrying_capacity_amplitude,
+            carrying_capacity_period,
+            carrying_capacity_schedule,
+            report_population_size,
+            female_freq_dep_coef,
+            female_preference,
+            assortative_mating,
+            number_mates,
+            mates_distribution,
+            sperm_precedence,
+            sperm_precedence_strength,
+            female_maturation_days,
+            male_maturation_days_aa,
+            male_maturation_days_ab,
+            male_maturation_days_bb,
+            maturation_cv,
+            environment_time,
+            environment_time_variation,
+            mutation_rate_a_to_b,
+            mutation_rate_b_to_a,
+            introduction_generation,
+            introduction_allele,
+            neutral_sites,
+            inversion_start,
+            inversion_end,
+            recombination_rate,
+            gene_flux_rate,
+            neutral_mutation_rate,
+            loci_file,
+            epistasis_file,
+            locus_combination,
+            inheritance,
+            survival_expression,
+            maturation_expression,
+            drive_k_females,
+            drive_k_males,
+            maternal_survival_aa,
+            maternal_survival_ab,
+            maternal_survival_bb,
+            maternal_maturation_aa,
+            maternal_maturation_ab,
+            maternal_maturation_bb,
+            maternal_maturation_slope,
+            carryover_survival,
+            carryover_maturation,
+            size_model,
+            male_size_aa,
+            male_size_ab,
+            male_size_bb,
+            male_size_sd,
+            size_maturation_intercept,
+            size_maturation_slope,
+            size_success_exponent,
+            development_model,
+            temperature_model,
+            temperature,
+            temperature_sd,
+            temperature_schedule,
+            reference_temperature,
+            development_threshold_aa,
+            development_threshold_ab,
+            development_threshold_bb,
+            effective_size,
+            report_hwe,
+            pedigree,
+            tree_sequence,
+            tree_sequence_samples,
+            vcf_samples,
+            vcf_generations,
+            seed,
+            checkpoint_interval,
+            resume,
+            stop_when_fixated,
+            quiet,
+        }
+    }
+
+    pub fn set(&mut self, name: &str, value: f64) -> Result<(), String> {
+        // Change one numeric parameter from its name
+        match name {
+            "number_generations" => self.number_generations = value as u32,
+            "proportion_females" => self.proportion_females = value,
+            "number_eggs_per_generation" => self.number_eggs_per_generation = value as usize,
+            "number_eggs_per_female" => self.number_eggs_per_female = value,
+            "proportion_aa" => self.proportion_aa = value,
+            "proportion_bb" => self.proportion_bb = value,
+            "survival_global" => self.survival_global = value,
+            "survival_females_aa" => self.survival_females_aa = value,
+            "survival_females_ab" => self.survival_females_ab = value,
+            "survival_females_bb" => self.survival_females_bb = value,
+            "survival_males_aa" => self.survival_males_aa = value,
+            "survival_males_ab" => self.survival_males_ab = value,
+            "survival_males_bb" => self.survival_males_bb = value,
+            "female_eggs_aa" => self.female_eggs_aa = value,
+            "female_eggs_ab" => self.female_eggs_ab = value,
+            "female_eggs_bb" => self.female_eggs_bb = value,
+            "male_success_aa" => self.male_success_aa = value,
+            "male_success_ab" => self.male_success_ab = value,
+            "male_success_bb" => self.male_success_bb = value,
+            "male_freq_dep_coef" => self.male_freq_dep_coef = value,
+            "male_freq_dep_exponent" => self.male_freq_dep_exponent = value,
+            "competition_aa" => self.competition_aa = value,
+            "competition_ab" => self.competition_ab = value,
+            "competition_bb" => self.competition_bb = value,
+            "carrying_capacity_amplitude" => self.carrying_capacity_amplitude = value,
+            "carrying_capacity_period" => self.carrying_capacity_period = value,
+            "female_freq_dep_coef" => self.female_freq_dep_coef = value,
+            "assortative_mating" => self.assortative_mating = value,
+            "number_mates" => self.number_mates = value,
+            "sperm_precedence_strength" => self.sperm_precedence_strength = value,
+            "female_maturation_days" => self.female_maturation_days = value,
+            "male_maturation_days_aa" => self.male_maturation_days_aa = value,
+            "male_maturation_days_ab" => self.male_maturation_days_ab = value,
+            "male_maturation_days_bb" => self.male_maturation_days_bb = value,
+            "maturation_cv" => self.maturation_cv = value,
+            "environment_time" => self.environment_time = value,
+            "environment_time_variation" => self.environment_time_variation = value,
+            "mutation_rate_a_to_b" => self.mutation_rate_a_to_b = value,
+            "mutation_rate_b_to_a" => self.mutation_rate_b_to_a = value,
+            "neutral_sites" => self.neutral_sites = value as usize,
+            "inversion_start" => self.inversion_start = value,
+            "inversion_end" => self.inversion_end = value,
+            "recombination_rate" => self.recombination_rate = value,
+            "gene_flux_rate" => self.gene_flux_rate = value,
+            "neutral_mutation_rate" => self.neutral_mutation_rate = value,
+            "drive_k_females" => self.drive_k_females = value,
+            "drive_k_males" => self.drive_k_males = value,
+            "maternal_survival_aa" => self.maternal_survival_aa = value,
+            "maternal_survival_ab" => self.maternal_survival_ab = value,
+            "maternal_survival_bb" => self.maternal_survival_bb = value,
+            "maternal_maturation_aa" => self.maternal_maturation_aa = value,
+            "maternal_maturation_ab" => self.maternal_maturation_ab = value,
+            "maternal_maturation_bb" => self.maternal_maturation_bb = value,
+            "maternal_maturation_slope" => self.maternal_maturation_slope = value,
+            "carryover_survival" => self.carryover_survival = value,
+            "carryover_maturation" => self.carryover_maturation = value,
+            "male_size_aa" => self.male_size_aa = value,
+            "male_size_ab" => self.male_size_ab = value,
+            "male_size_bb" => self.male_size_bb = value,
+            "male_size_sd" => self.male_size_sd = value,
+            "size_maturation_intercept" => self.size_maturation_intercept = value,
+            "size_maturation_slope" => self.size_maturation_slope = value,
+            "size_success_exponent" => self.size_success_exponent = value,
+            "temperature" => self.temperature = value,
+            "temperature_sd" => self.temperature_sd = value,
+            "reference_temperature" => self.reference_temperature = value,
+            "development_threshold_aa" => self.development_threshold_aa = value,
+            "development_threshold_ab" => self.development_threshold_ab = value,
+            "development_threshold_bb" => self.development_threshold_bb = value,
+            "tree_sequence_samples" => self.tree_sequence_samples = value as usize,
+            "vcf_samples" => self.vcf_samples = value as usize,
+            _ => return Err(format!("Unknown numeric parameter: {}", name)),
+        }
+
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Result<f64, String> {
+        // Value of one numeric parameter from its name
+        let value = match name {
+            "number_generations" => self.number_generations as f64,
+            "proportion_females" => self.proportion_females,
+            "number_eggs_per_generation" => self.number_eggs_per_generation as f64,
+            "number_eggs_per_female" => self.number_eggs_per_female,
+            "proportion_aa" => self.proportion_aa,
+            "proportion_bb" => self.proportion_bb,
+            "survival_global" => self.survival_global,
+            "survival_females_aa" => self.survival_females_aa,
+            "survival_females_ab" => self.survival_females_ab,
+            "survival_females_bb" => self.survival_females_bb,
+            "survival_males_aa" => self.survival_males_aa,
+            "survival_males_ab" => self.survival_males_ab,
+            "survival_males_bb" => self.survival_males_bb,
+            "female_eggs_aa" => self.female_eggs_aa,
+            "female_eggs_ab" => self.female_eggs_ab,
+            "female_eggs_bb" => self.female_eggs_bb,
+            "male_success_aa" => self.male_success_aa,
+            "male_success_ab" => self.male_success_ab,
+            "male_success_bb" => self.male_success_bb,
+            "male_freq_dep_coef" => self.male_freq_dep_coef,
+            "male_freq_dep_exponent" => self.male_freq_dep_exponent,
+            "competition_aa" => self.competition_aa,
+            "competition_ab" => self.competition_ab,
+            "competition_bb" => self.competition_bb,
+            "carrying_capacity_amplitude" => self.carrying_capacity_amplitude,
+            "carrying_capacity_period" => self.carrying_capacity_period,
+            "female_freq_dep_coef" => self.female_freq_dep_coef,
+            "assortative_mating" => self.assortative_mating,
+            "number_mates" => self.number_mates,
+            "sperm_precedence_strength" => self.sperm_precedence_strength,
+            "female_maturation_days" => self.female_maturation_days,
+            "male_maturation_days_aa" => self.male_maturation_days_aa,
+            "male_maturation_days_ab" => self.male_maturation_days_ab,
+            "male_maturation_days_bb" => self.male_maturation_days_bb,
+            "maturation_cv" => self.maturation_cv,
+            "environment_time" => self.environment_time,
+            "environment_time_variation" => self.environment_time_variation,
+            "mutation_rate_a_to_b" => self.mutation_rate_a_to_b,
+            "mutation_rate_b_to_a" => self.mutation_rate_b_to_a,
+            "neutral_sites" => self.neutral_sites as f64,
+            "inversion_start" => self.inversion_start,
+            "inversion_end" => self.inversion_end,
+            "recombination_rate" => self.recombination_rate,
+            "gene_flux_rate" => self.gene_flux_rate,
+            "neutral_mutation_rate" => self.neutral_mutation_rate,
+            "drive_k_females" => self.drive_k_females,
+            "drive_k_males" => self.drive_k_males,
+            "maternal_survival_aa" => self.maternal_survival_aa,
+            "maternal_survival_ab" => self.maternal_survival_ab,
+            "maternal_survival_bb" => self.maternal_survival_bb,
+            "maternal_maturation_aa" => self.maternal_maturation_aa,
+            "maternal_maturation_ab" => self.maternal_maturation_ab,
+            "maternal_maturation_bb" => self.maternal_maturation_bb,
+            "maternal_maturation_slope" => self.maternal_maturation_slope,
+            "carryover_survival" => self.carryover_survival,
+            "carryover_maturation" => self.carryover_maturation,
+            "male_size_aa" => self.male_size_aa,
+            "male_size_ab" => self.male_size_ab,
+            "male_size_bb" => self.male_size_bb,
+            "male_size_sd" => self.male_size_sd,
+            "size_maturation_intercept" => self.size_maturation_intercept,
+            "size_maturation_slope" => self.size_maturation_slope,
+            "size_success_exponent" => self.size_success_exponent,
+            "temperature" => self.temperature,
+            "temperature_sd" => self.temperature_sd,
+            "reference_temperature" => self.reference_temperature,
+            "development_threshold_aa" => self.development_threshold_aa,
+            "development_threshold_ab" => self.development_threshold_ab,
+            "development_threshold_bb" => self.development_threshold_bb,
+            "tree_sequence_samples" => self.tree_sequence_samples as f64,
+            "vcf_samples" => self.vcf_samples as f64,
+            _ => return Err(format!("Unknown numeric parameter: {}", name)),
+        };
+
+        Ok(value)
+    }
+}
+
+//// Functions
+fn parse_preference_matrix(text: &str) -> [[f64; 3]; 3] {
+    // Read 9 comma separated values into a female by male preference
+    // matrix. Rows are female AA, AB, BB and columns male AA, AB, BB
+    let values: Vec<f64> = text
+        .split(',')
+        .map(|v| {
+            v.trim()
+                .parse::<f64>()
+                .expect("Cannot parse preference value")
+        })
+        .collect();
+
+    if values.len() != 9 {
+        eprintln!(
+            "Female preference matrix needs 9 values, got {}",
+            values.len()
+        );
+        process::exit(1);
+    }
+
+    let mut matrix = [[0.0; 3]; 3];
+
+    for (i, value) in values.iter().enumerate() {
+        matrix[i / 3][i % 3] = *value;
+    }
+
+    matrix
+}
@@ -0,0 +1,68 @@
+//// Pedigree
+// Every egg surviving to adulthood receives an identifier and keeps those
+// of its mother and father. Each of its chromosomes also keeps the parent
+// it comes from and which of the two parent chromosomes it copies at the
+// inversion, chromosomes being ordered like neutral sites with the A
+// arrangement first in heterokaryotypes. Under X linkage, both chromosomes
+// of hemizygous males come from their mother. With `--pedigree`, adults
+// are written to a file with one row per individual, from which realised
+// reproductive success, family sizes and the ancestry of each arrangement
+// can be recovered. Identifier 0 stands for the unknown parents of the
+// first generation
+use std::collections::HashSet;
+use std::io::Write;
+
+use crate::neutral::chromosome_arrangements;
+use crate::Fly;
+
+pub const HEADER: &[u8] = b"id,generation,deme,sex,genotype,mother,father,\
+chromosome1Arrangement,chromosome1Parent,chromosome1ParentChromosome,\
+chromosome2Arrangement,chromosome2Parent,chromosome2ParentChromosome,mature\n";
+
+//// Functions
+pub fn write_adults(
+    outfile: &mut dyn Write,
+    adults: &[Fly],
+    mature_adults: &[Fly],
+    generation: u32,
+    deme: usize,
+) {
+    // One row per adult of a deme, telling whether it matured
+    let mature: HashSet<u64> = mature_adults.iter().map(|a| a.id).collect();
+
+    // Parent chromosomes are numbered from 1, 0 for unknown parents
+    let parent_chromosome = |origin: (u64, u8)| {
+        if origin.0 == 0 {
+            0
+        } else {
+            origin.1 + 1
+        }
+    };
+
+    for adult in adults.iter() {
+        let arrangements = chromosome_arrangements(adult.genotype);
+
+        outfile
+            .write_all(
+                format!(
+                    "{},{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
+                    adult.id,
+                    generation,
+                    deme + 1,
+                    adult.sex,
+                    adult.genotype,
+                    adult.parents[0],
+                    adult.parents[1],
+                    arrangements[0],
+                    adult.origins[0].0,
+                    parent_chromosome(adult.origins[0]),
+                    arrangements[1],
+                    adult.origins[1].0,
+                    parent_chromosome(adult.origins[1]),
+                    mature.contains(&adult.id)
+                )
+                .as_bytes(),
+            )
+            .expect("Cannot write to file");
+    }
+}
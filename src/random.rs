@@ -0,0 +1,109 @@
+//// Random number generation
+// Simulations draw from a xoshiro256** generator (Blackman and Vigna 2018)
+// instead of the thread generator, so a run is reproducible from `--seed`
+// and the state of the generator, four 64 bit words, can be saved to a
+// checkpoint and restored. Without a seed, the generator is seeded from
+// the thread generator. Subcommands repeating runs, `abc` and
+// `sensitivity`, sample parameters from `--seed` itself and give their
+// runs the following seeds, one per run
+use rand::{Error, RngCore, SeedableRng};
+
+//// Structs
+#[derive(Clone, Debug)]
+pub struct SimulationRng {
+    state: [u64; 4],
+}
+
+impl SimulationRng {
+    pub fn new(seed: Option<u64>) -> SimulationRng {
+        match seed {
+            Some(seed) => SimulationRng::seed_from_u64(seed),
+            None => SimulationRng::seed_from_u64(rand::random()),
+        }
+    }
+
+    pub fn state(&self) -> [u64; 4] {
+        self.state
+    }
+
+    pub fn from_state(state: [u64; 4]) -> SimulationRng {
+        SimulationRng { state }
+    }
+}
+
+impl RngCore for SimulationRng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+
+        result
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl SeedableRng for SimulationRng {
+    type Seed = [u8; 32];
+
+    fn from_seed(seed: [u8; 32]) -> SimulationRng {
+        let mut state = [0; 4];
+
+        for (s, chunk) in state.iter_mut().zip(seed.chunks(8)) {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(chunk);
+            *s = u64::from_le_bytes(bytes);
+        }
+
+        // The all zero state is a fixed point
+        if state == [0; 4] {
+            return SimulationRng::seed_from_u64(0);
+        }
+
+        SimulationRng { state }
+    }
+
+    fn seed_from_u64(seed: u64) -> SimulationRng {
+        // Expand the seed with SplitMix64, which never gives four zeros
+        let mut x = seed;
+        let mut state = [0; 4];
+
+        for s in state.iter_mut() {
+            x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = x;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            *s = z ^ (z >> 31);
+        }
+
+        SimulationRng { state }
+    }
+}
+
+//// Functions
+pub fn run_seed(seed: Option<u64>, run: u64) -> Option<u64> {
+    // Seed of one of the repeated runs of a subcommand, after the seed of
+    // its own sampling
+    seed.map(|s| s.wrapping_add(1).wrapping_add(run))
+}
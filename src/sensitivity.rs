@@ -0,0 +1,590 @@
+//// Global sensitivity analysis
+// The `sensitivity` subcommand varies parameters within user ranges and
+// computes first order and total Sobol indices of the final frequency of A
+// and of the fixation probability. Following Saltelli, two matrices A and
+// B of parameter values are sampled, either by Latin hypercube or from a
+// Sobol sequence, and each parameter i gives a matrix AB_i, equal to A
+// except for column i taken from B. With N base samples and k parameters,
+// N * (k + 2) parameter sets are each run `replicates` times. First order
+// indices use the Saltelli (2010) estimator and total indices the Jansen
+// estimator. Indices of an output without variance are left empty
+use clap::ArgMatches;
+use rand::prelude::*;
+use rand::seq::SliceRandom;
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::process;
+use std::str::FromStr;
+
+use crate::parameters::Parameters;
+use crate::random::{self, SimulationRng};
+use crate::simulation;
+
+// Direction numbers of the Sobol sequence from Joe and Kuo (2008), file
+// new-joe-kuo-6.21201: degree s and interior coefficients a of the
+// primitive polynomial, and initial direction numbers m of dimensions 2 to
+// 53, enough for 26 parameters
+const SOBOL_DIRECTIONS: [(u32, u32, &[u32]); 52] = [
+    (1, 0, &[1]),
+    (2, 1, &[1, 3]),
+    (3, 1, &[1, 3, 1]),
+    (3, 2, &[1, 1, 1]),
+    (4, 1, &[1, 1, 3, 3]),
+    (4, 4, &[1, 3, 5, 13]),
+    (5, 2, &[1, 1, 5, 5, 17]),
+    (5, 4, &[1, 1, 5, 5, 5]),
+    (5, 7, &[1, 1, 7, 11, 19]),
+    (5, 11, &[1, 1, 5, 1, 1]),
+    (5, 13, &[1, 1, 1, 3, 11]),
+    (5, 14, &[1, 3, 5, 5, 31]),
+    (6, 1, &[1, 3, 3, 9, 7, 49]),
+    (6, 13, &[1, 1, 1, 15, 21, 21]),
+    (6, 16, &[1, 3, 1, 13, 27, 49]),
+    (6, 19, &[1, 1, 1, 15, 7, 5]),
+    (6, 22, &[1, 3, 1, 15, 13, 25]),
+    (6, 25, &[1, 1, 5, 5, 19, 61]),
+    (7, 1, &[1, 3, 7, 11, 23, 15, 103]),
+    (7, 4, &[1, 3, 7, 13, 13, 15, 69]),
+    (7, 7, &[1, 1, 3, 13, 7, 35, 63]),
+    (7, 8, &[1, 3, 5, 9, 1, 25, 53]),
+    (7, 14, &[1, 3, 1, 13, 9, 35, 107]),
+    (7, 19, &[1, 3, 1, 5, 27, 61, 31]),
+    (7, 21, &[1, 1, 5, 11, 19, 41, 61]),
+    (7, 28, &[1, 3, 5, 3, 3, 13, 69]),
+    (7, 31, &[1, 1, 7, 13, 1, 19, 1]),
+    (7, 32, &[1, 3, 7, 5, 13, 19, 59]),
+    (7, 37, &[1, 1, 3, 9, 25, 29, 41]),
+    (7, 41, &[1, 3, 5, 13, 23, 1, 55]),
+    (7, 42, &[1, 3, 7, 3, 13, 59, 17]),
+    (7, 50, &[1, 3, 1, 3, 5, 53, 69]),
+    (7, 55, &[1, 1, 5, 5, 23, 33, 13]),
+    (7, 56, &[1, 1, 7, 7, 1, 61, 123]),
+    (7, 59, &[1, 1, 7, 9, 13, 61, 49]),
+    (7, 62, &[1, 3, 3, 5, 3, 55, 33]),
+    (8, 14, &[1, 3, 1, 15, 31, 13, 49, 245]),
+    (8, 21, &[1, 3, 5, 15, 31, 59, 63, 97]),
+    (8, 22, &[1, 3, 1, 11, 11, 11, 77, 249]),
+    (8, 38, &[1, 3, 1, 11, 27, 43, 71, 9]),
+    (8, 47, &[1, 1, 7, 15, 21, 11, 81, 45]),
+    (8, 49, &[1, 3, 7, 3, 25, 31, 65, 79]),
+    (8, 50, &[1, 3, 1, 1, 19, 11, 3, 205]),
+    (8, 52, &[1, 1, 5, 9, 19, 21, 29, 157]),
+    (8, 56, &[1, 3, 7, 11, 1, 33, 89, 185]),
+    (8, 67, &[1, 3, 3, 3, 15, 9, 79, 71]),
+    (8, 70, &[1, 3, 7, 11, 15, 39, 119, 27]),
+    (8, 84, &[1, 1, 3, 1, 11, 31, 97, 225]),
+    (8, 97, &[1, 1, 1, 3, 23, 43, 57, 177]),
+    (8, 103, &[1, 3, 7, 7, 17, 17, 37, 71]),
+    (8, 115, &[1, 3, 1, 5, 27, 63, 123, 213]),
+    (8, 122, &[1, 1, 3, 5, 11, 43, 53, 133]),
+];
+const SOBOL_BITS: usize = 32;
+
+// Names of the analysed outputs
+const OUTPUTS: [&str; 2] = ["finalFrequencyA", "fixationProbability"];
+
+//// Enums
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Sampling {
+    LatinHypercube,
+    Sobol,
+}
+
+impl FromStr for Sampling {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lhs" => Ok(Sampling::LatinHypercube),
+            "sobol" => Ok(Sampling::Sobol),
+            _ => Err(format!("Unknown sampling method: {}", s)),
+        }
+    }
+}
+
+//// Structs
+#[derive(Clone, Debug)]
+pub struct Range {
+    pub name: String,
+    pub low: f64,
+    pub high: f64,
+}
+
+impl FromStr for Range {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Parse `name=low:high`
+        let mut parts = s.splitn(2, '=');
+        let name = parts.next().unwrap_or("").trim().to_string();
+        let bounds: Vec<f64> = match parts.next() {
+            Some(bounds) => bounds
+                .split(':')
+                .map(|v| {
+                    v.trim()
+                        .parse::<f64>()
+                        .map_err(|_| format!("Cannot parse range value '{}' in: {}", v, s))
+                })
+                .collect::<Result<Vec<f64>, String>>()?,
+            None => Vec::new(),
+        };
+
+        if bounds.len() != 2 || bounds[1] < bounds[0] {
+            return Err(format!("Parameter range needs 'name=low:high': {}", s));
+        }
+
+        Ok(Range {
+            name,
+            low: bounds[0],
+            high: bounds[1],
+        })
+    }
+}
+
+pub struct SobolIndices {
+    pub first_order: Vec<f64>,
+    pub total: Vec<f64>,
+}
+
+//// Functions
+pub fn sobol_points(number_points: usize, dimensions: usize) -> Vec<Vec<f64>> {
+    // Points of a Sobol sequence in the unit hypercube, skipping the first
+    // point at the origin. The first dimension is the van der Corput
+    // sequence and the others use the Joe and Kuo direction numbers
+    let mut directions: Vec<[u32; SOBOL_BITS]> = Vec::new();
+
+    for d in 0..dimensions {
+        let mut v = [0u32; SOBOL_BITS];
+
+        if d == 0 {
+            for (k, direction) in v.iter_mut().enumerate() {
+                *direction = 1 << (SOBOL_BITS - 1 - k);
+            }
+        } else {
+            let (degree, a, m) = SOBOL_DIRECTIONS[d - 1];
+            let degree = degree as usize;
+
+            for (k, direction) in v.iter_mut().enumerate().take(degree) {
+                *direction = m[k] << (SOBOL_BITS - 1 - k);
+            }
+
+            for k in degree..SOBOL_BITS {
+                v[k] = v[k - degree] ^ (v[k - degree] >> degree);
+
+                for l in 1..degree {
+                    if (a >> (degree - 1 - l)) & 1 == 1 {
+                        v[k] ^= v[k - l];
+                    }
+                }
+            }
+        }
+
+        directions.push(v);
+    }
+
+    // Gray code ordering, each point differing from the previous one by
+    // the direction of the lowest zero bit of the previous index
+    let mut x = vec![0u32; dimensions];
+    let mut points = Vec::new();
+
+    for i in 0..number_points {
+        let bit = (!i).trailing_zeros() as usize;
+
+        for (value, v) in x.iter_mut().zip(directions.iter()) {
+            *value ^= v[bit];
+        }
+
+        points.push(
+            x.iter()
+                .map(|value| f64::from(*value) / 2f64.powi(SOBOL_BITS as i32))
+                .collect(),
+        );
+    }
+
+    points
+}
+
+pub fn latin_hypercube(
+    number_points: usize,
+    dimensions: usize,
+    rng: &mut SimulationRng,
+) -> Vec<Vec<f64>> {
+    // One point in each of `number_points` equal slices of every dimension
+    let mut points = vec![vec![0.0; dimensions]; number_points];
+
+    for d in 0..dimensions {
+        let mut slices: Vec<usize> = (0..number_points).collect();
+        slices.shuffle(rng);
+
+        for (point, slice) in points.iter_mut().zip(slices.iter()) {
+            point[d] = (*slice as f64 + rng.gen::<f64>()) / number_points as f64;
+        }
+    }
+
+    points
+}
+
+pub fn sobol_indices(f_a: &[f64], f_b: &[f64], f_ab: &[Vec<f64>]) -> Option<SobolIndices> {
+    // First order and total indices of each parameter from the outputs of
+    // matrices A, B and AB_i, none when the output never varies
+    let n = f_a.len() as f64;
+    let all: Vec<f64> = f_a.iter().chain(f_b.iter()).cloned().collect();
+    let mean = all.iter().sum::<f64>() / all.len() as f64;
+    let variance = all.iter().map(|f| (f - mean).powi(2)).sum::<f64>() / all.len() as f64;
+
+    if variance == 0.0 || variance.is_nan() {
+        return None;
+    }
+
+    let first_order = f_ab
+        .iter()
+        .map(|f_abi| {
+            (0..f_a.len())
+                .map(|j| f_b[j] * (f_abi[j] - f_a[j]))
+                .sum::<f64>()
+                / n
+                / variance
+        })
+        .collect();
+
+    let total = f_ab
+        .iter()
+        .map(|f_abi| {
+            (0..f_a.len())
+                .map(|j| (f_a[j] - f_abi[j]).powi(2))
+                .sum::<f64>()
+                / (2.0 * n)
+                / variance
+        })
+        .collect();
+
+    Some(SobolIndices { first_order, total })
+}
+
+fn evaluate(
+    base: &Parameters,
+    ranges: &[Range],
+    point: &[f64],
+    replicates: usize,
+    first_run: u64,
+) -> [f64; 2] {
+    // Mean final frequency of A and fixation probability over replicates
+    // of one parameter set, seeded from the number of its first run
+    let mut params = base.clone();
+
+    for (range, value) in ranges.iter().zip(point.iter()) {
+        if let Err(e) = params.set(&range.name, range.low + value * (range.high - range.low)) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+
+    let mut frequency_a = 0.0;
+    let mut fixations = 0;
+
+    for replicate in 0..replicates {
+        params.seed = random::run_seed(base.seed, first_run + replicate as u64);
+        let summary = simulation::run_summary(&params);
+        frequency_a += summary.final_frequency_a();
+
+        if summary.fixated() {
+            fixations += 1;
+        }
+    }
+
+    [
+        frequency_a / replicates as f64,
+        f64::from(fixations) / replicates as f64,
+    ]
+}
+
+pub fn main(base: &Parameters, matches: &ArgMatches) {
+    // Sample parameter sets, run them and write Sobol indices
+    let ranges: Vec<Range> = matches
+        .values_of("range")
+        .map(|values| values.collect::<Vec<&str>>())
+        .unwrap_or_default()
+        .iter()
+        .map(|v| {
+            let range = v.parse::<Range>().unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                process::exit(1);
+            });
+
+            if let Err(e) = base.get(&range.name) {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+
+            range
+        })
+        .collect();
+
+    if ranges.is_empty() {
+        eprintln!("Sensitivity analysis needs at least one --range");
+        process::exit(1);
+    }
+
+    let sampling = matches
+        .value_of("sampling")
+        .unwrap_or("sobol")
+        .parse::<Sampling>()
+        .unwrap();
+
+    let number_samples = matches
+        .value_of("samples")
+        .unwrap_or("100")
+        .parse::<usize>()
+        .unwrap();
+
+    let replicates = matches
+        .value_of("replicates")
+        .unwrap_or("10")
+        .parse::<usize>()
+        .unwrap()
+        .max(1);
+
+    // Matrices A and B from the first and last k dimensions
+    let k = ranges.len();
+
+    let sobol_dimensions = SOBOL_DIRECTIONS.len() + 1;
+
+    if sampling == Sampling::Sobol && 2 * k > sobol_dimensions {
+        eprintln!(
+            "Sobol sampling is limited to {} parameters, use --sampling lhs",
+            sobol_dimensions / 2
+        );
+        process::exit(1);
+    }
+
+    let points = match sampling {
+        Sampling::LatinHypercube => {
+            latin_hypercube(number_samples, 2 * k, &mut SimulationRng::new(base.seed))
+        }
+        Sampling::Sobol => sobol_points(number_samples, 2 * k),
+    };
+
+    let matrix_a: Vec<Vec<f64>> = points.iter().map(|p| p[..k].to_vec()).collect();
+    let matrix_b: Vec<Vec<f64>> = points.iter().map(|p| p[k..].to_vec()).collect();
+
+    // Run all parameter sets, writing them with their outputs
+    let mut samples_file: Box<dyn Write> = match matches.value_of("samples_file") {
+        Some(path) => Box::new(File::create(path).expect("Cannot creat file")),
+        None => Box::new(io::sink()),
+    };
+
+    let names: Vec<&str> = ranges.iter().map(|r| r.name.as_str()).collect();
+    writeln!(
+        samples_file,
+        "matrix,sample,{},{}",
+        names.join(","),
+        OUTPUTS.join(",")
+    )
+    .expect("Cannot write to file");
+
+    let mut number_runs = 0;
+    let mut run_matrix = |label: &str, matrix: &[Vec<f64>]| -> Vec<[f64; 2]> {
+        matrix
+            .iter()
+            .enumerate()
+            .map(|(j, point)| {
+                let outputs = evaluate(base, &ranges, point, replicates, number_runs);
+                number_runs += replicates as u64;
+                let values: Vec<String> = ranges
+                    .iter()
+                    .zip(point.iter())
+                    .map(|(r, v)| (r.low + v * (r.high - r.low)).to_string())
+                    .collect();
+
+                writeln!(
+                    samples_file,
+                    "{},{},{},{},{}",
+                    label,
+                    j + 1,
+                    values.join(","),
+                    outputs[0],
+                    outputs[1]
+                )
+                .expect("Cannot write to file");
+
+                outputs
+            })
+            .collect()
+    };
+
+    let f_a = run_matrix("A", &matrix_a);
+    let f_b = run_matrix("B", &matrix_b);
+    let f_ab: Vec<Vec<[f64; 2]>> = (0..k)
+        .map(|i| {
+            let matrix_abi: Vec<Vec<f64>> = matrix_a
+                .iter()
+                .zip(matrix_b.iter())
+                .map(|(a, b)| {
+                    let mut abi = a.clone();
+                    abi[i] = b[i];
+                    abi
+                })
+                .collect();
+
+            run_matrix(&format!("AB_{}", ranges[i].name), &matrix_abi)
+        })
+        .collect();
+
+    // Indices of each parameter for each output
+    let mut indices_file: Box<dyn Write> = match matches.value_of("indices_file") {
+        Some(path) => Box::new(File::create(path).expect("Cannot creat file")),
+        None => Box::new(io::stdout()),
+    };
+
+    writeln!(indices_file, "parameter,output,firstOrder,total").expect("Cannot write to file");
+
+    for (o, output) in OUTPUTS.iter().enumerate() {
+        let select = |values: &[[f64; 2]]| -> Vec<f64> { values.iter().map(|v| v[o]).collect() };
+        let f_ab_output: Vec<Vec<f64>> = f_ab.iter().map(|f| select(f)).collect();
+        let indices = sobol_indices(&select(&f_a), &select(&f_b), &f_ab_output);
+
+        // Indices are undefined, and left empty, when every run gives the
+        // same output
+        if indices.is_none() {
+            eprintln!(
+                "Output {} is the same in every run, its Sobol indices are undefined",
+                output
+            );
+        }
+
+        for (i, range) in ranges.iter().enumerate() {
+            let (first_order, total) = match &indices {
+                Some(indices) => (
+                    indices.first_order[i].to_string(),
+                    indices.total[i].to_string(),
+                ),
+                None => (String::new(), String::new()),
+            };
+
+            writeln!(
+                indices_file,
+                "{},{},{},{}",
+                range.name, output, first_order, total
+            )
+            .expect("Cannot write to file");
+        }
+    }
+}
+
+//// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn sobol_points_match_joe_kuo() {
+        // First points after the origin of the first three dimensions
+        let expected = [
+            [0.5, 0.5, 0.5],
+            [0.75, 0.25, 0.25],
+            [0.25, 0.75, 0.75],
+            [0.375, 0.375, 0.625],
+            [0.875, 0.875, 0.125],
+            [0.625, 0.125, 0.875],
+            [0.125, 0.625, 0.375],
+        ];
+
+        let points = sobol_points(expected.len(), 3);
+
+        for (point, expected) in points.iter().zip(expected.iter()) {
+            assert_eq!(point.as_slice(), expected);
+        }
+    }
+
+    #[test]
+    fn sobol_dimensions_are_stratified() {
+        // With the origin, the first 2^m points of every dimension fall one
+        // in each interval of width 2^-m
+        let dimensions = SOBOL_DIRECTIONS.len() + 1;
+        let points = sobol_points(255, dimensions);
+
+        for m in 1..=8 {
+            let number = 1 << m;
+
+            for d in 0..dimensions {
+                let mut seen = vec![false; number];
+                seen[0] = true;
+
+                for point in points.iter().take(number - 1) {
+                    let interval = (point[d] * number as f64) as usize;
+                    assert!(!seen[interval], "dimension {} with 2^{} points", d + 1, m);
+                    seen[interval] = true;
+                }
+            }
+        }
+    }
+
+    fn estimate(k: usize, model: impl Fn(&[f64]) -> f64) -> SobolIndices {
+        // Indices of a model of k parameters from 2^13 Sobol samples
+        let points = sobol_points(8192, 2 * k);
+        let matrix_a: Vec<Vec<f64>> = points.iter().map(|p| p[..k].to_vec()).collect();
+        let matrix_b: Vec<Vec<f64>> = points.iter().map(|p| p[k..].to_vec()).collect();
+        let f_a: Vec<f64> = matrix_a.iter().map(|x| model(x)).collect();
+        let f_b: Vec<f64> = matrix_b.iter().map(|x| model(x)).collect();
+        let f_ab: Vec<Vec<f64>> = (0..k)
+            .map(|i| {
+                matrix_a
+                    .iter()
+                    .zip(matrix_b.iter())
+                    .map(|(a, b)| {
+                        let mut abi = a.clone();
+                        abi[i] = b[i];
+                        model(&abi)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        sobol_indices(&f_a, &f_b, &f_ab).unwrap()
+    }
+
+    fn assert_indices(obtained: &[f64], expected: &[f64], tolerance: f64) {
+        for (o, e) in obtained.iter().zip(expected.iter()) {
+            assert!(
+                (o - e).abs() < tolerance,
+                "{:?} is not {:?}",
+                obtained,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn linear_model_indices() {
+        // Y = x1 + 2 x2 + 3 x3 has no interactions, each index being the
+        // share of its squared coefficient
+        let indices = estimate(3, |x| x[0] + 2.0 * x[1] + 3.0 * x[2]);
+        let expected = [1.0 / 14.0, 4.0 / 14.0, 9.0 / 14.0];
+
+        assert_indices(&indices.first_order, &expected, 0.01);
+        assert_indices(&indices.total, &expected, 0.01);
+    }
+
+    #[test]
+    fn ishigami_indices() {
+        // Ishigami function with a = 7 and b = 0.1 on [-pi, pi]^3, whose
+        // third parameter only acts through its interaction with the first
+        let indices = estimate(3, |x| {
+            let x: Vec<f64> = x.iter().map(|v| PI * (2.0 * v - 1.0)).collect();
+            x[0].sin() + 7.0 * x[1].sin().powi(2) + 0.1 * x[2].powi(4) * x[0].sin()
+        });
+
+        assert_indices(&indices.first_order, &[0.3139, 0.4424, 0.0], 0.02);
+        assert_indices(&indices.total, &[0.5576, 0.4424, 0.2437], 0.02);
+    }
+
+    #[test]
+    fn constant_output_has_no_indices() {
+        let constant = vec![0.25; 16];
+        let f_ab = vec![constant.clone(), constant.clone()];
+
+        assert!(sobol_indices(&constant, &constant, &f_ab).is_none());
+    }
+}
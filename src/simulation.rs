@@ -0,0 +1,1702 @@
+//// Simulation through generations
+// A `Model` holds the survival and reproduction values derived from one set
+// of parameters and implements each step of a generation. `run` iterates
+// over generations for one or more demes, each with its own model
+use rand::distributions::Uniform;
+use rand::prelude::*;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Write};
+
+use crate::body_size::{self, BodySize, SizeModel};
+use crate::checkpoint::{self, OutputFiles};
+use crate::demography::CarryingCapacity;
+use crate::effective_size::{self, DriftTracker, VarianceNe};
+use crate::freq_dep::{self, FrequencyDependence};
+use crate::hardy_weinberg::HardyWeinberg;
+use crate::loci::{Component, Loci};
+use crate::maternal::{MaternalEffects, Mother};
+use crate::metapopulation::{Migration, MigrationModel, MigrationStage};
+use crate::neutral::{offspring_chromosomes, Linkage};
+use crate::parameters::Parameters;
+use crate::pedigree;
+use crate::random::SimulationRng;
+use crate::temperature::Development;
+use crate::tree_sequence::Genealogy;
+use crate::vcf::VcfSampler;
+use crate::{
+    create_first_generation, density_survival, father_from_mates, genotype_from_alleles,
+    genotype_index, get_genotype_proportions, introduce_allele, mutate_allele, number_of_mates,
+    report_genotypes,
+};
+use crate::{
+    DensityModel, Expression, Fly, Genotype, Inheritance, Lifestage, ProportionGenotype,
+    ProportionSexe, Sex,
+};
+
+//// Structs
+pub struct Model {
+    pub params: Parameters,
+    egg_survival: HashMap<(Sex, Genotype), f64>,
+    female_eggs: HashMap<(Sex, Genotype), f64>,
+    male_success: HashMap<Genotype, f64>,
+    maturation_time: HashMap<(Sex, Genotype), f64>,
+    competition: HashMap<Genotype, f64>,
+    mate_preference: [[f64; 3]; 3],
+    male_freq_dep_model: Box<dyn FrequencyDependence>,
+    female_freq_dep_model: freq_dep::NegativeOwn,
+    carrying_capacity: CarryingCapacity,
+    pub linkage: Linkage,
+    pub loci: Loci,
+    maternal_effects: MaternalEffects,
+    pub body_size: BodySize,
+    development: Development,
+}
+
+impl Model {
+    pub fn new(params: &Parameters) -> Model {
+        //// Survival and reproduction parameters
+        // Survival from egg to adult
+        let mut egg_survival: HashMap<(Sex, Genotype), f64> = HashMap::new();
+        egg_survival.insert((Sex::Female, Genotype::AA), params.survival_females_aa);
+        egg_survival.insert((Sex::Female, Genotype::AB), params.survival_females_ab);
+        egg_survival.insert((Sex::Female, Genotype::BB), params.survival_females_bb);
+        egg_survival.insert((Sex::Male, Genotype::AA), params.survival_males_aa);
+        egg_survival.insert((Sex::Male, Genotype::AB), params.survival_males_ab);
+        egg_survival.insert((Sex::Male, Genotype::BB), params.survival_males_bb);
+
+        // Relative number of eggs per female genotype
+        let mut female_eggs: HashMap<(Sex, Genotype), f64> = HashMap::new();
+        female_eggs.insert((Sex::Female, Genotype::AA), params.female_eggs_aa);
+        female_eggs.insert((Sex::Female, Genotype::AB), params.female_eggs_ab);
+        female_eggs.insert((Sex::Female, Genotype::BB), params.female_eggs_bb);
+
+        // Carrying capacity per generation
+        let carrying_capacity = CarryingCapacity {
+            model: params.carrying_capacity_model,
+            base: params.number_eggs_per_generation as f64,
+            amplitude: params.carrying_capacity_amplitude,
+            period: params.carrying_capacity_period,
+            schedule: params.carrying_capacity_schedule.clone(),
+        };
+
+        // Competitive effect of larvae per genotype
+        let mut competition: HashMap<Genotype, f64> = HashMap::new();
+        competition.insert(Genotype::AA, params.competition_aa);
+        competition.insert(Genotype::AB, params.competition_ab);
+        competition.insert(Genotype::BB, params.competition_bb);
+
+        // Frequency dependence on female fecundity
+        let female_freq_dep_model = freq_dep::NegativeOwn {
+            coef: params.female_freq_dep_coef,
+        };
+
+        // Male reproductive sucess per genotype
+        let mut male_success: HashMap<Genotype, f64> = HashMap::new();
+        male_success.insert(Genotype::AA, params.male_success_aa);
+        male_success.insert(Genotype::AB, params.male_success_ab);
+        male_success.insert(Genotype::BB, params.male_success_bb);
+
+        // Frequency dependence on male reproductive success
+        let male_freq_dep_model = freq_dep::from_model(
+            params.male_freq_dep_model,
+            params.male_freq_dep_coef,
+            params.male_freq_dep_exponent,
+            params.male_freq_dep_table.as_deref(),
+        );
+
+        // Female mate preference, including assortative mating
+        let mut mate_preference = params.female_preference;
+
+        for (i, row) in mate_preference.iter_mut().enumerate() {
+            row[i] *= 1.0 + params.assortative_mating;
+        }
+
+        // Maturation time
+        let mut maturation_time: HashMap<(Sex, Genotype), f64> = HashMap::new();
+        maturation_time.insert((Sex::Female, Genotype::AA), params.female_maturation_days);
+        maturation_time.insert((Sex::Female, Genotype::AB), params.female_maturation_days);
+        maturation_time.insert((Sex::Female, Genotype::BB), params.female_maturation_days);
+        maturation_time.insert((Sex::Male, Genotype::AA), params.male_maturation_days_aa);
+        maturation_time.insert((Sex::Male, Genotype::AB), params.male_maturation_days_ab);
+        maturation_time.insert((Sex::Male, Genotype::BB), params.male_maturation_days_bb);
+
+        // Genotype effects limited to one sex
+        limit_expression(&mut egg_survival, params.survival_expression);
+        limit_expression(&mut maturation_time, params.maturation_expression);
+
+        Model {
+            params: params.clone(),
+            egg_survival,
+            female_eggs,
+            male_success,
+            maturation_time,
+            competition,
+            mate_preference,
+            male_freq_dep_model,
+            female_freq_dep_model,
+            carrying_capacity,
+            linkage: Linkage::new(params),
+            loci: Loci::new(
+                params.loci_file.as_deref(),
+                params.epistasis_file.as_deref(),
+                params.locus_combination,
+                params.inheritance,
+            ),
+            maternal_effects: MaternalEffects::new(params),
+            body_size: BodySize::new(params),
+            development: Development::new(params),
+        }
+    }
+
+    fn individual_male_success(&self) -> bool {
+        // Males of a genotype differ in reproductive success
+        self.loci.is_active() || self.body_size.is_active()
+    }
+
+    fn male_success_of(&self, male: &Fly) -> f64 {
+        // Reproductive success of one male given his size and all loci
+        let success = if self.body_size.is_active() {
+            self.body_size.success(male.size)
+        } else {
+            self.male_success[&male.genotype]
+        };
+
+        self.loci.combine(Component::MaleSuccess, success, male)
+    }
+
+    pub fn capacity(&self, generation: u32) -> f64 {
+        self.carrying_capacity.at(generation)
+    }
+
+    pub fn first_generation(&self, rng: &mut SimulationRng) -> Vec<Fly> {
+        // Compute derived parameters
+        let proportion_ab = 1.0 - self.params.proportion_aa - self.params.proportion_bb;
+        let proportion_males = 1.0 - self.params.proportion_females;
+
+        // Proportions for weighted sampling with `choose_weighted`
+        let proportion_sexes = vec![
+            ProportionSexe {
+                sex: Sex::Female,
+                proportion: self.params.proportion_females,
+            },
+            ProportionSexe {
+                sex: Sex::Male,
+                proportion: proportion_males,
+            },
+        ];
+
+        let proportion_genotypes = vec![
+            ProportionGenotype {
+                genotype: Genotype::AA,
+                proportion: self.params.proportion_aa,
+            },
+            ProportionGenotype {
+                genotype: Genotype::AB,
+                proportion: proportion_ab,
+            },
+            ProportionGenotype {
+                genotype: Genotype::BB,
+                proportion: self.params.proportion_bb,
+            },
+        ];
+
+        let number_adults = self.capacity(0) * self.params.survival_global;
+        let number_adults = number_adults as u32;
+
+        let mut adults =
+            create_first_generation(number_adults, &proportion_sexes, &proportion_genotypes, rng);
+
+        // Random neutral sites and additional loci on each chromosome
+
+        // Hemizygous males carry a single arrangement under X linkage
+        if self.params.inheritance == Inheritance::XLinked {
+            for adult in adults.iter_mut() {
+                if adult.sex == Sex::Male && adult.genotype == Genotype::AB {
+                    adult.genotype = if rng.gen::<f64>() < 0.5 {
+                        Genotype::AA
+                    } else {
+                        Genotype::BB
+                    };
+                }
+            }
+        }
+
+        if self.linkage.is_active() {
+            for adult in adults.iter_mut() {
+                adult.haplotypes = [
+                    self.linkage.random_haplotype(rng),
+                    self.linkage.random_haplotype(rng),
+                ];
+
+                if self.linkage.hemizygous(adult) {
+                    adult.haplotypes[1] = adult.haplotypes[0];
+                }
+            }
+        }
+
+        if self.body_size.is_active() {
+            for adult in adults.iter_mut() {
+                adult.size = self.body_size.sample(adult.sex, adult.genotype, rng);
+            }
+        }
+
+        if self.loci.is_active() {
+            for adult in adults.iter_mut() {
+                adult.loci = [
+                    self.loci.random_chromosome(rng),
+                    self.loci.random_chromosome(rng),
+                ];
+
+                if self.linkage.hemizygous(adult) {
+                    adult.loci[1] = adult.loci[0];
+                }
+            }
+        }
+
+        adults
+    }
+
+    pub fn survive_eggs(&self, eggs: &[Fly], generation: u32, rng: &mut SimulationRng) -> Vec<Fly> {
+        // Egg survival by sex and genotype, and larval density
+        let mut adults = Vec::new();
+        let survival_density = density_survival(
+            eggs,
+            self.params.egg_density_model,
+            &self.competition,
+            self.capacity(generation),
+        );
+
+        for egg in eggs.iter() {
+            let random_number: f64 = rng.gen();
+
+            let egg_survival = self.loci.combine(
+                Component::EggSurvival,
+                self.egg_survival[&(egg.sex, egg.genotype)],
+                egg,
+            );
+
+            if random_number
+                < egg_survival
+                    * self.maternal_effects.survival(egg)
+                    * self.params.survival_global
+                    * survival_density
+            {
+                adults.push(*egg);
+            }
+        }
+
+        adults
+    }
+
+    pub fn mature(&self, adults: &[Fly], generation: u32, rng: &mut SimulationRng) -> Vec<Fly> {
+        //// Survival to reproduction
+        // Wrack temperature of this generation
+        let temperature = self.development.temperature_at(generation, rng);
+
+        // Environment duration
+        let mut mature_adults = Vec::new();
+        let environment_duration_min: f64 =
+            self.params.environment_time - self.params.environment_time_variation;
+        let environment_duration_max: f64 =
+            self.params.environment_time + self.params.environment_time_variation;
+        let environment_range = Uniform::from(environment_duration_min..environment_duration_max);
+
+        for adult in adults.iter() {
+            // Environment duration
+            let environment_duration = environment_range.sample(rng);
+
+            // Sample development time
+            let adult_maturation = if self.body_size.is_active() && adult.sex == Sex::Male {
+                self.body_size.maturation(adult.size)
+            } else {
+                self.maturation_time[&(adult.sex, adult.genotype)]
+            };
+            let adult_maturation =
+                adult_maturation * self.loci.combine(Component::Maturation, 1.0, adult);
+            let adult_maturation =
+                (adult_maturation + self.maternal_effects.maturation(adult)).max(0.0);
+            let adult_maturation =
+                self.development
+                    .maturation(adult_maturation, adult.genotype, temperature);
+
+            // Too cold to ever mature
+            if adult_maturation.is_infinite() {
+                continue;
+            }
+
+            // Flies whose effects shorten maturation to nothing, or without
+            // variation, mature without sampling an empty range
+            let adult_maturation_cv = adult_maturation * self.params.maturation_cv;
+            let m = if adult_maturation_cv > 0.0 {
+                let adult_maturation_lower = adult_maturation - adult_maturation_cv;
+                let adult_maturation_upper = adult_maturation + adult_maturation_cv;
+                let adult_maturation_range =
+                    Uniform::from(adult_maturation_lower..adult_maturation_upper);
+                let m1 = adult_maturation_range.sample(rng);
+                let m2 = adult_maturation_range.sample(rng);
+                let m3 = adult_maturation_range.sample(rng);
+                (m1 * m2 * m3).powf(1.0 / 3.0)
+            } else {
+                adult_maturation
+            };
+
+            // Decide survival, remembering conditions passed on to eggs
+            if environment_duration >= m {
+                let mut mature_adult = *adult;
+                mature_adult.maturation = m;
+                mature_adult.environment = environment_duration;
+                mature_adults.push(mature_adult);
+            }
+        }
+
+        mature_adults
+    }
+
+    pub fn reproduce(&self, mature_adults: &[Fly], rng: &mut SimulationRng) -> Option<Vec<Fly>> {
+        // Return the eggs laid by mature females, or None if there are no
+        // males to mate with
+        let mut individual_eggs = Vec::new();
+        let mature_females: Vec<Fly> = mature_adults
+            .iter()
+            .filter(|a| a.sex == Sex::Female)
+            .cloned()
+            .collect();
+        let mature_males: Vec<Fly> = mature_adults
+            .iter()
+            .filter(|a| a.sex == Sex::Male)
+            .cloned()
+            .collect();
+
+        //// Reproduction
+        // Count male genotypes
+        let number_mature_males = mature_males.len();
+        let mut male_genotype_counts: HashMap<&Genotype, f64> = HashMap::new();
+        male_genotype_counts.insert(&Genotype::AA, 0.0);
+        male_genotype_counts.insert(&Genotype::AB, 0.0);
+        male_genotype_counts.insert(&Genotype::BB, 0.0);
+
+        let mut males_by_genotype: HashMap<Genotype, Vec<Fly>> = HashMap::new();
+
+        for male in mature_males.iter() {
+            *male_genotype_counts.entry(&male.genotype).or_insert(0.0) += 1.0;
+            males_by_genotype
+                .entry(male.genotype)
+                .or_default()
+                .push(*male);
+        }
+
+        // Mean reproductive success of the males of each genotype
+        let mut male_success: HashMap<Genotype, f64> = self.male_success.clone();
+
+        if self.individual_male_success() {
+            for (genotype, males) in males_by_genotype.iter() {
+                let total: f64 = males.iter().map(|m| self.male_success_of(m)).sum();
+                male_success.insert(*genotype, total / males.len() as f64);
+            }
+        }
+
+        // Make them into proportions
+        let mut male_genotype_proportions: HashMap<&Genotype, f64> = HashMap::new();
+
+        for (genotype, count) in male_genotype_counts.iter() {
+            let proportion: f64 = count / (number_mature_males as f64);
+            male_genotype_proportions.insert(genotype, proportion);
+        }
+
+        // Compute frequency dependent selection coefficient
+        let mut male_freq_dep: HashMap<&Genotype, f64> = HashMap::new();
+        let freq_dep_coefficients = self.male_freq_dep_model.coefficients(&[
+            male_genotype_proportions[&Genotype::AA],
+            male_genotype_proportions[&Genotype::AB],
+            male_genotype_proportions[&Genotype::BB],
+        ]);
+
+        male_freq_dep.insert(&Genotype::AA, freq_dep_coefficients[0]);
+        male_freq_dep.insert(&Genotype::AB, freq_dep_coefficients[1]);
+        male_freq_dep.insert(&Genotype::BB, freq_dep_coefficients[2]);
+
+        // Compute male genotype probabilities for mating as function of
+        // genotype proportions, reproduction success of each genotype, and
+        // frequency dependent selection
+        let mut male_genotype_probabilities: HashMap<&Genotype, f64> = HashMap::new();
+        male_genotype_probabilities.insert(
+            &Genotype::AA,
+            male_genotype_proportions[&Genotype::AA]
+                * male_success[&Genotype::AA]
+                * male_freq_dep[&Genotype::AA],
+        );
+
+        male_genotype_probabilities.insert(
+            &Genotype::AB,
+            male_genotype_proportions[&Genotype::AB]
+                * male_success[&Genotype::AB]
+                * male_freq_dep[&Genotype::AB],
+        );
+
+        male_genotype_probabilities.insert(
+            &Genotype::BB,
+            male_genotype_proportions[&Genotype::BB]
+                * male_success[&Genotype::BB]
+                * male_freq_dep[&Genotype::BB],
+        );
+
+        // Normalize probabilities to 1.0, summing them in a fixed order for
+        // runs to be reproducible
+        let total_coefficient: f64 = male_genotype_probabilities[&Genotype::AA]
+            + male_genotype_probabilities[&Genotype::AB]
+            + male_genotype_probabilities[&Genotype::BB];
+
+        let proportion_genotypes = [
+            ProportionGenotype {
+                genotype: Genotype::AA,
+                proportion: male_genotype_probabilities[&Genotype::AA] / total_coefficient,
+            },
+            ProportionGenotype {
+                genotype: Genotype::AB,
+                proportion: male_genotype_probabilities[&Genotype::AB] / total_coefficient,
+            },
+            ProportionGenotype {
+                genotype: Genotype::BB,
+                proportion: male_genotype_probabilities[&Genotype::BB] / total_coefficient,
+            },
+        ];
+
+        // No reproduction if one of proportion_genotypes is NaN
+        if proportion_genotypes.iter().any(|p| p.proportion.is_nan()) {
+            return None;
+        }
+
+        // Weight male genotype probabilities by the preference of each
+        // female genotype
+        let mut female_mate_probabilities: HashMap<&Genotype, [ProportionGenotype; 3]> =
+            HashMap::new();
+
+        for female_genotype in [Genotype::AA, Genotype::AB, Genotype::BB].iter() {
+            let preference = self.mate_preference[genotype_index(*female_genotype)];
+            let mut probabilities = proportion_genotypes;
+
+            for p in probabilities.iter_mut() {
+                p.proportion *= preference[genotype_index(p.genotype)];
+            }
+
+            female_mate_probabilities.insert(female_genotype, probabilities);
+        }
+
+        // Frequency dependent fecundity of female genotypes
+        let female_genotypes = get_genotype_proportions(&mature_females);
+        let female_fecundity = self.female_freq_dep_model.coefficients(&female_genotypes);
+
+        // Each female reproduces with one or more males
+        for female in mature_females.iter() {
+            // Females that reject all available males do not reproduce
+            let mate_probabilities = female_mate_probabilities[&female.genotype];
+
+            if mate_probabilities.iter().all(|p| p.proportion <= 0.0) {
+                continue;
+            }
+
+            // Pick weighted random mate genotypes, in mating order
+            let num_mates = number_of_mates(
+                self.params.number_mates,
+                self.params.mates_distribution,
+                rng,
+            );
+            let mates: Vec<Fly> = (0..num_mates)
+                .map(|_| {
+                    let genotype = mate_probabilities
+                        .choose_weighted(rng, |item| item.proportion)
+                        .unwrap()
+                        .genotype;
+
+                    // Pick one male of the chosen genotype, weighted by his
+                    // own success
+                    let males = &males_by_genotype[&genotype];
+
+                    if self.individual_male_success() {
+                        *males
+                            .choose_weighted(rng, |m| self.male_success_of(m))
+                            .unwrap()
+                    } else {
+                        *males.choose(rng).unwrap()
+                    }
+                })
+                .collect();
+
+            // Determine number of eggs to lay
+            let female_eggs = self.loci.combine(
+                Component::FemaleEggs,
+                self.female_eggs[&(female.sex, female.genotype)],
+                female,
+            );
+            let num_eggs = (self.params.number_eggs_per_female
+                * female_eggs
+                * female_fecundity[genotype_index(female.genotype)])
+                as u32;
+
+            for _ in 1..=num_eggs {
+                // Get one female gamete
+                let (female_chromosome, female_allele, female_haplotype) =
+                    self.linkage.gamete(female, rng);
+                let female_loci = self.loci.gamete(female, female_chromosome, rng);
+                let female_allele = mutate_allele(
+                    female_allele,
+                    self.params.mutation_rate_a_to_b,
+                    self.params.mutation_rate_b_to_a,
+                    rng,
+                );
+
+                // Get one male gamete from the mate siring this egg
+                let father = father_from_mates(
+                    &mates,
+                    self.params.sperm_precedence,
+                    self.params.sperm_precedence_strength,
+                    |mate| self.male_success_of(mate),
+                    rng,
+                );
+                let (male_chromosome, male_allele, male_haplotype) =
+                    self.linkage.gamete(&father, rng);
+                let male_loci = self.loci.gamete(&father, male_chromosome, rng);
+                let male_allele = mutate_allele(
+                    male_allele,
+                    self.params.mutation_rate_a_to_b,
+                    self.params.mutation_rate_b_to_a,
+                    rng,
+                );
+
+                let random_number: f64 = rng.gen();
+
+                let sex = if random_number < self.params.proportion_females {
+                    Sex::Female
+                } else {
+                    Sex::Male
+                };
+
+                // Under X linkage, sons only carry the chromosome of their
+                // mother, hemizygous males being stored as homozygotes
+                let female_origin = (female.id, female_chromosome as u8);
+                let male_origin = (father.id, male_chromosome as u8);
+                let (male_allele, male_haplotype, male_loci, male_origin) =
+                    if sex == Sex::Male && self.params.inheritance == Inheritance::XLinked {
+                        (female_allele, female_haplotype, female_loci, female_origin)
+                    } else {
+                        (male_allele, male_haplotype, male_loci, male_origin)
+                    };
+
+                // Create egg from parent gametes
+                let genotype = genotype_from_alleles(female_allele, male_allele);
+                let haplotypes = offspring_chromosomes(
+                    female_allele,
+                    female_haplotype,
+                    male_haplotype,
+                    genotype,
+                );
+                let loci = offspring_chromosomes(female_allele, female_loci, male_loci, genotype);
+                let origins =
+                    offspring_chromosomes(female_allele, female_origin, male_origin, genotype);
+
+                individual_eggs.push(Fly {
+                    sex,
+                    genotype,
+                    haplotypes,
+                    loci,
+                    maturation: 0.0,
+                    environment: 0.0,
+                    mother: Some(Mother::of(female)),
+                    size: self.body_size.sample(sex, genotype, rng),
+                    id: 0,
+                    parents: [female.id, father.id],
+                    origins,
+                });
+            }
+        }
+
+        Some(individual_eggs)
+    }
+
+    pub fn regulate_eggs(
+        &self,
+        mut individual_eggs: Vec<Fly>,
+        generation: u32,
+        rng: &mut SimulationRng,
+    ) -> Vec<Fly> {
+        // Shuffle and keep as many eggs as the carrying capacity of the
+        // next generation, unless larval density regulates survival instead
+        if self.params.egg_density_model == DensityModel::None {
+            individual_eggs.shuffle(rng);
+            let number_eggs = individual_eggs.len();
+            let next_capacity = self.capacity(generation + 1) as usize;
+
+            let keep_n_eggs = if number_eggs < next_capacity {
+                number_eggs
+            } else {
+                next_capacity
+            };
+
+            individual_eggs.truncate(keep_n_eggs);
+        }
+
+        individual_eggs
+    }
+
+    pub fn hwe(&self, flies: &[Fly], previous: &[HardyWeinberg]) -> Vec<HardyWeinberg> {
+        // Hardy-Weinberg diagnostics of earlier stages followed by those of
+        // `flies`, or none when they are not reported
+        if !self.params.report_hwe {
+            return Vec::new();
+        }
+
+        let mut hwe = previous.to_vec();
+        hwe.push(HardyWeinberg::of(flies, self.params.inheritance));
+        hwe
+    }
+
+    pub fn expected_adult_proportions(&self) -> Vec<[f64; 3]> {
+        // Deterministic recursion of genotype proportions in an infinite
+        // population, returning the proportions of mature adults in each
+        // generation. Drift, additional loci, body size, maternal effects and
+        // introductions are ignored and stochastic temperatures take their
+        // mean. The recursion stops when no female or male matures
+        let sexes = [Sex::Female, Sex::Male];
+        let genotypes = [Genotype::AA, Genotype::AB, Genotype::BB];
+        let proportion_females = self.params.proportion_females;
+        let x_linked = self.params.inheritance == Inheritance::XLinked;
+        let mut proportions = Vec::new();
+
+        // Adults of the first generation by sex and genotype
+        let initial = [
+            self.params.proportion_aa,
+            1.0 - self.params.proportion_aa - self.params.proportion_bb,
+            self.params.proportion_bb,
+        ];
+        let mut adults = [[0.0; 3]; 2];
+
+        for (j, p) in initial.iter().enumerate() {
+            adults[0][j] = proportion_females * p;
+            adults[1][j] = (1.0 - proportion_females) * p;
+        }
+
+        if x_linked {
+            adults[1][0] += adults[1][1] / 2.0;
+            adults[1][2] += adults[1][1] / 2.0;
+            adults[1][1] = 0.0;
+        }
+
+        // Frequency of A in gametes of each genotype, after drive and
+        // recurrent mutation
+        let gametes_a = |transmission_b: f64| {
+            let mut frequencies = [1.0, 1.0 - transmission_b, 0.0];
+
+            for f in frequencies.iter_mut() {
+                *f = *f * (1.0 - self.params.mutation_rate_a_to_b)
+                    + (1.0 - *f) * self.params.mutation_rate_b_to_a;
+            }
+
+            frequencies
+        };
+        let female_gametes_a = gametes_a(self.params.drive_k_females);
+        let male_gametes_a = gametes_a(self.params.drive_k_males);
+
+        let mut maturation = [[0.0; 3]; 2];
+        let mut last_temperature = None;
+
+        for gen in 0..=self.params.number_generations {
+            // Probability to mature before the end of the environment,
+            // updated when the temperature changes
+            let temperature = self.development.mean_temperature_at(gen);
+
+            if last_temperature != Some(temperature) {
+                for (s, sex) in sexes.iter().enumerate() {
+                    for (j, genotype) in genotypes.iter().enumerate() {
+                        let days = self.development.maturation(
+                            self.maturation_time[&(*sex, *genotype)],
+                            *genotype,
+                            temperature,
+                        );
+                        maturation[s][j] = maturation_probability(
+                            days,
+                            self.params.maturation_cv,
+                            self.params.environment_time,
+                            self.params.environment_time_variation,
+                        );
+                    }
+                }
+
+                last_temperature = Some(temperature);
+            }
+
+            let mut mature = [[0.0; 3]; 2];
+
+            for s in 0..2 {
+                for j in 0..3 {
+                    mature[s][j] = adults[s][j] * maturation[s][j];
+                }
+            }
+
+            let number_females: f64 = mature[0].iter().sum();
+            let number_males: f64 = mature[1].iter().sum();
+
+            if number_females <= 0.0 && number_males <= 0.0 {
+                break;
+            }
+
+            let number_mature = number_females + number_males;
+            proportions.push([
+                (mature[0][0] + mature[1][0]) / number_mature,
+                (mature[0][1] + mature[1][1]) / number_mature,
+                (mature[0][2] + mature[1][2]) / number_mature,
+            ]);
+
+            if number_females <= 0.0 || number_males <= 0.0 {
+                break;
+            }
+
+            // Male genotype probabilities for mating
+            let male_proportions = [
+                mature[1][0] / number_males,
+                mature[1][1] / number_males,
+                mature[1][2] / number_males,
+            ];
+            let male_freq_dep = self.male_freq_dep_model.coefficients(&male_proportions);
+            let mut male_probabilities = [0.0; 3];
+
+            for (j, genotype) in genotypes.iter().enumerate() {
+                male_probabilities[j] =
+                    male_proportions[j] * self.male_success[genotype] * male_freq_dep[j];
+            }
+
+            // Frequency dependent fecundity of female genotypes
+            let female_proportions = [
+                mature[0][0] / number_females,
+                mature[0][1] / number_females,
+                mature[0][2] / number_females,
+            ];
+            let female_fecundity = self.female_freq_dep_model.coefficients(&female_proportions);
+
+            // Eggs from each pair of parent genotypes
+            let mut eggs = [[0.0; 3]; 2];
+
+            for (f, female_genotype) in genotypes.iter().enumerate() {
+                let preference = self.mate_preference[f];
+                let mut mates = [0.0; 3];
+
+                for (g, mate) in mates.iter_mut().enumerate() {
+                    *mate = male_probabilities[g] * preference[g];
+                }
+
+                let total_mates: f64 = mates.iter().sum();
+
+                if total_mates.is_nan() || total_mates <= 0.0 {
+                    continue;
+                }
+
+                let number_eggs = mature[0][f]
+                    * self.female_eggs[&(Sex::Female, *female_genotype)]
+                    * female_fecundity[f];
+                let female_a = female_gametes_a[f];
+
+                for (g, mate) in mates.iter().enumerate() {
+                    let number_eggs = number_eggs * mate / total_mates;
+                    let male_a = male_gametes_a[g];
+                    let daughters = [
+                        female_a * male_a,
+                        female_a * (1.0 - male_a) + (1.0 - female_a) * male_a,
+                        (1.0 - female_a) * (1.0 - male_a),
+                    ];
+
+                    // Sons are hemizygous for their mother's allele under X
+                    // linkage
+                    let sons = if x_linked {
+                        [female_a, 0.0, 1.0 - female_a]
+                    } else {
+                        daughters
+                    };
+
+                    for j in 0..3 {
+                        eggs[0][j] += number_eggs * proportion_females * daughters[j];
+                        eggs[1][j] += number_eggs * (1.0 - proportion_females) * sons[j];
+                    }
+                }
+            }
+
+            // Egg survival, rescaled as proportions
+            for (s, sex) in sexes.iter().enumerate() {
+                for (j, genotype) in genotypes.iter().enumerate() {
+                    adults[s][j] = eggs[s][j] * self.egg_survival[&(*sex, *genotype)];
+                }
+            }
+
+            let number_adults: f64 = adults.iter().flatten().sum();
+
+            if number_adults.is_nan() || number_adults <= 0.0 {
+                break;
+            }
+
+            for a in adults.iter_mut().flatten() {
+                *a /= number_adults;
+            }
+        }
+
+        proportions
+    }
+}
+
+//// Enums
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum OutputLayout {
+    // One file of genotype proportions per deme
+    PerDeme,
+    // One file of allele frequencies by deme position along a coastline
+    Cline,
+    // No output, only the returned summary, for repeated runs
+    Summary,
+}
+
+//// Structs
+pub struct Deme {
+    pub model: Model,
+    pub eggs: Vec<Fly>,
+    pub adults: Vec<Fly>,
+    pub mature_adults: Vec<Fly>,
+    pub number_eggs_laid: usize,
+    pub eggs_laid_proportions: [f64; 3],
+    pub eggs_laid_hwe: Vec<HardyWeinberg>,
+    outfile: Box<dyn Write>,
+}
+
+impl Deme {
+    fn new(params: &Parameters, outfile: Box<dyn Write>, rng: &mut SimulationRng) -> Deme {
+        let model = Model::new(params);
+        let adults = model.first_generation(rng);
+
+        Deme {
+            model,
+            eggs: Vec::new(),
+            adults,
+            mature_adults: Vec::new(),
+            number_eggs_laid: 0,
+            eggs_laid_proportions: [0.0; 3],
+            eggs_laid_hwe: Vec::new(),
+            outfile,
+        }
+    }
+}
+
+pub struct RunSummary {
+    // Genotype proportions of mature adults of all demes per generation,
+    // and whether the run ended early without males to reproduce
+    pub adult_proportions: Vec<[f64; 3]>,
+    pub collapsed: bool,
+}
+
+impl RunSummary {
+    fn save(&self, checkpoint: &mut checkpoint::Writer) {
+        checkpoint.value(self.adult_proportions.len());
+        checkpoint.end_line();
+
+        for proportions in self.adult_proportions.iter() {
+            for p in proportions.iter() {
+                checkpoint.float(*p);
+            }
+
+            checkpoint.end_line();
+        }
+    }
+
+    fn restore(&mut self, checkpoint: &mut checkpoint::Reader) {
+        let number: usize = checkpoint.value();
+        self.adult_proportions = (0..number)
+            .map(|_| [checkpoint.float(), checkpoint.float(), checkpoint.float()])
+            .collect();
+    }
+
+    fn add_generation(&mut self, demes: &[Deme]) {
+        let adults: Vec<Fly> = demes
+            .iter()
+            .flat_map(|d| d.mature_adults.iter().cloned())
+            .collect();
+        self.adult_proportions
+            .push(get_genotype_proportions(&adults));
+    }
+
+    pub fn final_frequency_a(&self) -> f64 {
+        // Frequency of A in the last generation, 0 for an extinct
+        // population
+        match self.adult_proportions.last() {
+            Some(proportions) => allele_frequency_a(proportions),
+            None => 0.0,
+        }
+    }
+
+    pub fn fixated(&self) -> bool {
+        let frequency_a = self.final_frequency_a();
+        frequency_a <= 0.0 || frequency_a >= 1.0
+    }
+}
+
+//// Functions
+fn limit_expression(values: &mut HashMap<(Sex, Genotype), f64>, expression: Expression) {
+    // Replace the values of the sex not expressing genotype effects by
+    // their mean over genotypes
+    let silent_sexes: &[Sex] = match expression {
+        Expression::Both => &[],
+        Expression::Females => &[Sex::Male],
+        Expression::Males => &[Sex::Female],
+    };
+
+    let genotypes = [Genotype::AA, Genotype::AB, Genotype::BB];
+
+    for sex in silent_sexes.iter() {
+        let mean = genotypes.iter().map(|g| values[&(*sex, *g)]).sum::<f64>() / 3.0;
+
+        for genotype in genotypes.iter() {
+            values.insert((*sex, *genotype), mean);
+        }
+    }
+}
+
+fn deme_header(params: &Parameters) -> Vec<u8> {
+    // Header of the output file of a deme
+    let mut header = b"Generation,eggAA,eggAB,eggBB,adultAA,adultAB,adultBB".to_vec();
+
+    if params.report_population_size {
+        header.extend_from_slice(b",eggNumber,adultNumber,carryingCapacity");
+    }
+
+    if params.report_hwe {
+        header.extend_from_slice(b",eggHo,eggHe,eggFis,eggHweP,adultHo,adultHe,adultFis,adultHweP");
+    }
+
+    header.push(b'\n');
+    header
+}
+
+pub fn insert_before_extension(output_file: &str, suffix: &str) -> String {
+    // Add a suffix to a file name, before its extension if it has one
+    let name_start = output_file.rfind('/').map(|i| i + 1).unwrap_or(0);
+
+    match output_file[name_start..].rfind('.') {
+        Some(i) => format!(
+            "{}{}{}",
+            &output_file[..name_start + i],
+            suffix,
+            &output_file[name_start + i..]
+        ),
+        None => format!("{}{}", output_file, suffix),
+    }
+}
+
+pub fn deme_output_file(output_file: &str, deme: usize, number_demes: usize) -> String {
+    // Output file of one deme, named by adding the deme number before the
+    // extension when there is more than one deme
+    if number_demes == 1 {
+        return output_file.to_string();
+    }
+
+    insert_before_extension(output_file, &format!("_deme{}", deme + 1))
+}
+
+fn report_effective_size_summary(ne_file: &mut Option<File>, drift: &[DriftTracker]) {
+    // Harmonic mean of variance effective sizes and last temporal effective
+    // size of each deme, on rows of generation `all`
+    if let Some(ne_file) = ne_file.as_mut() {
+        for (i, tracker) in drift.iter().enumerate() {
+            ne_file
+                .write_all(
+                    format!(
+                        "all,{},NA,NA,NA,{},{}\n",
+                        i + 1,
+                        tracker.harmonic_mean_variance_ne(),
+                        tracker.temporal_ne
+                    )
+                    .as_bytes(),
+                )
+                .expect("Cannot write to file");
+        }
+    }
+}
+
+fn deme_mature_adults(demes: &[Deme]) -> Vec<(usize, Fly)> {
+    // Mature adults of all demes with their deme
+    demes
+        .iter()
+        .enumerate()
+        .flat_map(|(i, d)| d.mature_adults.iter().map(move |a| (i, *a)))
+        .collect()
+}
+
+fn migrate_stage(demes: &mut [Deme], migration: &Migration, rng: &mut SimulationRng) {
+    // Move eggs or mature adults between demes
+    let groups: Vec<Vec<Fly>> = demes
+        .iter_mut()
+        .map(|d| match migration.stage {
+            MigrationStage::Egg => std::mem::take(&mut d.eggs),
+            MigrationStage::Adult => std::mem::take(&mut d.mature_adults),
+        })
+        .collect();
+
+    for (deme, group) in demes.iter_mut().zip(migration.migrate(groups, rng)) {
+        match migration.stage {
+            MigrationStage::Egg => deme.eggs = group,
+            MigrationStage::Adult => deme.mature_adults = group,
+        }
+    }
+}
+
+fn maturation_probability(
+    days: f64,
+    cv: f64,
+    environment_time: f64,
+    environment_time_variation: f64,
+) -> f64 {
+    // Probability that the development time sampled in `mature`, the
+    // geometric mean of three uniform values, ends within a uniform
+    // environment duration. Integrated over a grid of the three values
+    if days.is_infinite() {
+        return 0.0;
+    }
+
+    let number_points = 12;
+    let values: Vec<f64> = (0..number_points)
+        .map(|i| days * (1.0 - cv + 2.0 * cv * (i as f64 + 0.5) / number_points as f64))
+        .collect();
+    let mut total = 0.0;
+
+    for m1 in values.iter() {
+        for m2 in values.iter() {
+            for m3 in values.iter() {
+                let m = (m1 * m2 * m3).powf(1.0 / 3.0);
+
+                total += if environment_time_variation > 0.0 {
+                    ((environment_time + environment_time_variation - m)
+                        / (2.0 * environment_time_variation))
+                        .clamp(0.0, 1.0)
+                } else if environment_time >= m {
+                    1.0
+                } else {
+                    0.0
+                };
+            }
+        }
+    }
+
+    total / (number_points as f64).powi(3)
+}
+
+fn allele_frequency_a(genotypes: &[f64; 3]) -> f64 {
+    // Frequency of the A allele from AA, AB, and BB proportions
+    genotypes[0] + genotypes[1] / 2.0
+}
+
+pub fn run_summary(params: &Parameters) -> RunSummary {
+    // Run one population without writing output, for repeated runs
+    let migration = Migration::new(
+        MigrationModel::Island,
+        0.0,
+        0.0,
+        1,
+        None,
+        MigrationStage::Adult,
+    );
+
+    run(
+        params,
+        std::slice::from_ref(params),
+        &migration,
+        "",
+        OutputLayout::Summary,
+    )
+}
+
+pub fn run(
+    params: &Parameters,
+    demes_parameters: &[Parameters],
+    migration: &Migration,
+    output_file: &str,
+    output_layout: OutputLayout,
+) -> RunSummary {
+    // Global options are taken from the base parameters
+    let number_demes = demes_parameters.len();
+    let experiment_name = &params.experiment_name;
+    let writes_output = output_layout != OutputLayout::Summary;
+    let quiet = params.quiet || !writes_output;
+    let mut summary = RunSummary {
+        adult_proportions: Vec::new(),
+        collapsed: false,
+    };
+
+    // Initialize random number generation
+    let mut rng = SimulationRng::new(params.seed);
+
+    // Continue an interrupted run from its checkpoint, if it reached one
+    let checkpoint_path = checkpoint::checkpoint_path(output_file);
+    let fingerprint = checkpoint::fingerprint(params, demes_parameters);
+
+    let mut checkpoint = if writes_output && params.resume {
+        let checkpoint = checkpoint::Reader::open(&checkpoint_path, fingerprint);
+
+        if checkpoint.is_none() {
+            eprintln!(
+                "No checkpoint {}, starting from the first generation",
+                checkpoint_path.display()
+            );
+        }
+
+        checkpoint
+    } else {
+        None
+    };
+
+    let mut outputs = OutputFiles::new(checkpoint.as_mut());
+
+    // Along a coastline, all positions are reported in a single file
+    let mut cline_file = match output_layout {
+        OutputLayout::PerDeme | OutputLayout::Summary => None,
+        OutputLayout::Cline => Some(outputs.open(
+            output_file.to_string(),
+            b"Generation,Position,environmentTime,eggNumber,eggA,adultNumber,adultA\n",
+        )),
+    };
+
+    // Diversity at neutral sites linked to the inversion
+    let mut neutral_file = if writes_output && params.neutral_sites > 0 {
+        Some(outputs.open(
+            insert_before_extension(output_file, "_neutral"),
+            b"Generation,Deme,Region,chromosomesA,chromosomesB,piA,piB,dxy,fst\n",
+        ))
+    } else {
+        None
+    };
+
+    // Allele frequencies at additional loci
+    let mut loci_file = if writes_output && params.loci_file.is_some() {
+        Some(outputs.open(
+            insert_before_extension(output_file, "_loci"),
+            b"Generation,Deme,Locus,frequencyB,linkageDisequilibrium\n",
+        ))
+    } else {
+        None
+    };
+
+    // Male body size distribution per genotype
+    let mut size_file = if writes_output && params.size_model == SizeModel::Trait {
+        Some(outputs.open(
+            insert_before_extension(output_file, "_size"),
+            b"Generation,Deme,Genotype,number,mean,sd,min,q25,median,q75,max\n",
+        ))
+    } else {
+        None
+    };
+
+    // Variance and temporal effective population sizes
+    let mut ne_file = if writes_output && params.effective_size {
+        Some(outputs.open(
+            insert_before_extension(output_file, "_ne"),
+            b"Generation,Deme,parents,meanOffspring,varianceOffspring,varianceNe,temporalNe\n",
+        ))
+    } else {
+        None
+    };
+
+    // Adults with their parents
+    let mut pedigree_file = if writes_output && params.pedigree {
+        Some(outputs.open(
+            insert_before_extension(output_file, "_pedigree"),
+            pedigree::HEADER,
+        ))
+    } else {
+        None
+    };
+
+    // Genealogy of chromosomes at the inversion
+    let mut genealogy = if writes_output && params.tree_sequence {
+        Some(Genealogy::new(params.inheritance))
+    } else {
+        None
+    };
+
+    // Genotypes of sampled adults
+    let mut vcf_sampler = if writes_output && params.vcf_samples > 0 {
+        Some(VcfSampler::new(params))
+    } else {
+        None
+    };
+
+    //// Generate first generation of eggs
+    let mut demes: Vec<Deme> = demes_parameters
+        .iter()
+        .enumerate()
+        .map(|(i, p)| {
+            let outfile: Box<dyn Write> = match output_layout {
+                OutputLayout::PerDeme => Box::new(outputs.open(
+                    deme_output_file(output_file, i, number_demes),
+                    &deme_header(p),
+                )),
+                OutputLayout::Cline | OutputLayout::Summary => Box::new(io::sink()),
+            };
+            Deme::new(p, outfile, &mut rng)
+        })
+        .collect();
+
+    // Identifiers of adults, 0 being kept for unknown parents
+    let mut next_id: u64 = 1;
+    let mut drift: Vec<DriftTracker> = demes.iter().map(|_| DriftTracker::default()).collect();
+
+    // State of the run at the checkpoint, in the order it was saved
+    let mut first_generation = 0;
+
+    if let Some(checkpoint) = checkpoint.as_mut() {
+        first_generation = checkpoint.generation;
+        rng = checkpoint.rng();
+        next_id = checkpoint.value();
+        summary.restore(checkpoint);
+
+        for deme in demes.iter_mut() {
+            deme.eggs = checkpoint.flies();
+        }
+
+        for tracker in drift.iter_mut() {
+            tracker.restore(checkpoint);
+        }
+
+        if let Some(genealogy) = genealogy.as_mut() {
+            genealogy.restore(checkpoint);
+        }
+
+        if let Some(vcf_sampler) = vcf_sampler.as_mut() {
+            vcf_sampler.restore(checkpoint);
+        }
+    }
+
+    //// Iterate over generations
+    if !quiet {
+        if number_demes > 1 {
+            print!("#Deme\t");
+        }
+
+        if params.report_hwe {
+            println!("#Gen\tStage\tNum\tAA\tAB\tBB\tHo\tHe\tFis\tHweP");
+        } else {
+            println!("#Gen\tStage\tNum\tAA\tAB\tBB");
+        }
+    }
+
+    let mut last_generation = 0;
+
+    for gen in first_generation..=params.number_generations {
+        last_generation = gen;
+
+        for (i, deme) in demes.iter_mut().enumerate() {
+            // Egg survival to adulthood (except generation 1)
+            if gen != 0 {
+                deme.adults = deme.model.survive_eggs(&deme.eggs, gen, &mut rng);
+            }
+
+            // One-time introduction of an allele in the first deme
+            if i == 0 && params.introduction_generation == Some(gen) {
+                introduce_allele(
+                    &mut deme.adults,
+                    params.introduction_allele,
+                    params.inheritance,
+                    &mut rng,
+                );
+            }
+
+            for adult in deme.adults.iter_mut() {
+                adult.id = next_id;
+                next_id += 1;
+            }
+
+            if let Some(genealogy) = genealogy.as_mut() {
+                genealogy.add_adults(&deme.adults, gen);
+            }
+
+            // Report egg genotypes and cleanup
+            if !quiet && number_demes > 1 {
+                print!("{}\t", i + 1);
+            }
+
+            deme.eggs_laid_hwe = deme.model.hwe(&deme.eggs, &[]);
+            report_genotypes(
+                &deme.eggs,
+                gen,
+                Lifestage::Egg,
+                &mut deme.outfile,
+                quiet,
+                None,
+                &deme.eggs_laid_hwe,
+            );
+            deme.number_eggs_laid = deme.eggs.len();
+            deme.eggs_laid_proportions = get_genotype_proportions(&deme.eggs);
+            deme.eggs.clear();
+
+            // Survival to reproduction
+            deme.mature_adults = deme.model.mature(&deme.adults, gen, &mut rng);
+
+            if let Some(pedigree_file) = pedigree_file.as_mut() {
+                pedigree::write_adults(pedigree_file, &deme.adults, &deme.mature_adults, gen, i);
+            }
+        }
+
+        if let Some(genealogy) = genealogy.as_mut() {
+            genealogy.end_generation();
+        }
+
+        // Offspring of the previous adults, wherever they are
+        let variance_ne: Vec<Option<VarianceNe>> = if ne_file.is_some() {
+            let offspring = effective_size::offspring_numbers(
+                demes.iter().flat_map(|d| d.adults.iter()),
+                &demes[0].model.linkage,
+            );
+
+            drift
+                .iter_mut()
+                .zip(demes.iter())
+                .map(|(tracker, deme)| {
+                    let variance_ne = tracker.variance(&offspring);
+                    tracker.set_parents(&deme.adults);
+                    variance_ne
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        // Adults disperse before mating
+        if migration.is_active() && migration.stage == MigrationStage::Adult {
+            migrate_stage(&mut demes, migration, &mut rng);
+        }
+
+        summary.add_generation(&demes);
+
+        if let Some(vcf_sampler) = vcf_sampler.as_mut() {
+            if vcf_sampler.samples_generation(gen) {
+                vcf_sampler.sample(&deme_mature_adults(&demes), gen, &mut rng);
+            }
+        }
+
+        for (i, deme) in demes.iter_mut().enumerate() {
+            // Report adult genotypes
+            let population_size = if deme.model.params.report_population_size {
+                Some((deme.number_eggs_laid, deme.model.capacity(gen)))
+            } else {
+                None
+            };
+
+            if !quiet && number_demes > 1 {
+                print!("{}\t", i + 1);
+            }
+
+            let hwe = deme.model.hwe(&deme.mature_adults, &deme.eggs_laid_hwe);
+            report_genotypes(
+                &deme.mature_adults,
+                gen,
+                Lifestage::Adult,
+                &mut deme.outfile,
+                quiet,
+                population_size,
+                &hwe,
+            );
+
+            // Reproduction. A single population without males ends the
+            // simulation while an empty deme can be recolonised
+            let eggs = match deme.model.reproduce(&deme.mature_adults, &mut rng) {
+                Some(eggs) => eggs,
+                None if number_demes == 1 => {
+                    summary.collapsed = true;
+                    report_effective_size_summary(&mut ne_file, &drift);
+
+                    if let Some(genealogy) = genealogy.as_ref() {
+                        genealogy.write(
+                            output_file,
+                            &deme.mature_adults,
+                            params.tree_sequence_samples,
+                            &mut rng,
+                        );
+                    }
+
+                    if let Some(vcf_sampler) = vcf_sampler.as_mut() {
+                        if vcf_sampler.samples_last_generation() {
+                            let mature_adults: Vec<(usize, Fly)> =
+                                deme.mature_adults.iter().map(|a| (0, *a)).collect();
+                            vcf_sampler.sample(&mature_adults, gen, &mut rng);
+                        }
+
+                        vcf_sampler.write(output_file, &deme.model.linkage);
+                    }
+
+                    if writes_output {
+                        print!("{}\t", experiment_name);
+                        report_genotypes(
+                            &deme.mature_adults,
+                            gen,
+                            Lifestage::Adult,
+                            &mut deme.outfile,
+                            false,
+                            None,
+                            &hwe,
+                        );
+                    }
+
+                    if writes_output {
+                        checkpoint::remove(&checkpoint_path);
+                    }
+
+                    return summary;
+                }
+                None => Vec::new(),
+            };
+
+            deme.eggs = deme.model.regulate_eggs(eggs, gen, &mut rng);
+        }
+
+        // Report neutral diversity of mature adults
+        if let Some(neutral_file) = neutral_file.as_mut() {
+            for (i, deme) in demes.iter().enumerate() {
+                for stats in deme.model.linkage.statistics(&deme.mature_adults) {
+                    neutral_file
+                        .write_all(
+                            format!(
+                                "{},{},{},{},{},{},{},{},{}\n",
+                                gen,
+                                i + 1,
+                                stats.region,
+                                stats.chromosomes_a,
+                                stats.chromosomes_b,
+                                stats.pi_a,
+                                stats.pi_b,
+                                stats.dxy,
+                                stats.fst
+                            )
+                            .as_bytes(),
+                        )
+                        .expect("Cannot write to file");
+                }
+            }
+        }
+
+        // Report additional loci of mature adults
+        if let Some(loci_file) = loci_file.as_mut() {
+            for (i, deme) in demes.iter().enumerate() {
+                let loci = &deme.model.loci;
+
+                for (locus, (frequency_b, disequilibrium)) in
+                    loci.loci.iter().zip(loci.statistics(&deme.mature_adults))
+                {
+                    loci_file
+                        .write_all(
+                            format!(
+                                "{},{},{},{},{}\n",
+                                gen,
+                                i + 1,
+                                locus.name,
+                                frequency_b,
+                                disequilibrium
+                            )
+                            .as_bytes(),
+                        )
+                        .expect("Cannot write to file");
+                }
+            }
+        }
+
+        // Report body size of mature males
+        if let Some(size_file) = size_file.as_mut() {
+            for (i, deme) in demes.iter().enumerate() {
+                for d in deme.model.body_size.distribution(&deme.mature_adults) {
+                    size_file
+                        .write_all(
+                            format!(
+                                "{},{},{},{},{},{},{},{},{},{},{}\n",
+                                gen,
+                                i + 1,
+                                d.genotype,
+                                d.number,
+                                body_size::optional_value(d.mean),
+                                body_size::optional_value(d.sd),
+                                body_size::optional_value(d.min),
+                                body_size::optional_value(d.q25),
+                                body_size::optional_value(d.median),
+                                body_size::optional_value(d.q75),
+                                body_size::optional_value(d.max)
+                            )
+                            .as_bytes(),
+                        )
+                        .expect("Cannot write to file");
+                }
+            }
+        }
+
+        // Report effective population sizes since the previous and the
+        // first generation
+        if let Some(ne_file) = ne_file.as_mut() {
+            for (i, (deme, tracker)) in demes.iter().zip(drift.iter_mut()).enumerate() {
+                let temporal_ne = tracker.temporal(&deme.mature_adults, &deme.model.linkage, gen);
+
+                if let Some(v) = &variance_ne[i] {
+                    ne_file
+                        .write_all(
+                            format!(
+                                "{},{},{},{},{},{},{}\n",
+                                gen,
+                                i + 1,
+                                v.parents,
+                                v.mean_offspring,
+                                v.variance_offspring,
+                                v.ne,
+                                temporal_ne
+                            )
+                            .as_bytes(),
+                        )
+                        .expect("Cannot write to file");
+                }
+            }
+        }
+
+        // Report allele frequencies by position along the coastline
+        if let Some(cline_file) = cline_file.as_mut() {
+            for (i, deme) in demes.iter().enumerate() {
+                let adult_genotypes = get_genotype_proportions(&deme.mature_adults);
+
+                cline_file
+                    .write_all(
+                        format!(
+                            "{},{},{},{},{},{},{}\n",
+                            gen,
+                            i + 1,
+                            deme.model.params.environment_time,
+                            deme.number_eggs_laid,
+                            allele_frequency_a(&deme.eggs_laid_proportions),
+                            deme.mature_adults.len(),
+                            allele_frequency_a(&adult_genotypes)
+                        )
+                        .as_bytes(),
+                    )
+                    .expect("Cannot write to file");
+            }
+        }
+
+        // Eggs disperse after being laid
+        if migration.is_active() && migration.stage == MigrationStage::Egg {
+            migrate_stage(&mut demes, migration, &mut rng);
+        }
+
+        // Count genotypes to decide if we end the simulation
+        // because alleles are fixated
+        if params.stop_when_fixated {
+            let mut count_aa = 0;
+            let mut count_ab = 0;
+            let mut count_bb = 0;
+
+            for egg in demes.iter().flat_map(|d| d.eggs.iter()) {
+                match egg.genotype {
+                    Genotype::AA => count_aa += 1,
+                    Genotype::AB => count_ab += 1,
+                    Genotype::BB => count_bb += 1,
+                }
+            }
+
+            // End simulation if either AA or BB alleles get fixated
+            let fixated = (count_aa == 0 || count_bb == 0) && count_ab == 0;
+
+            if writes_output && (fixated || gen == params.number_generations) {
+                for deme in demes.iter_mut() {
+                    //println!("Alleles fixated on generation {}!", gen);
+                    print!("{}\t", experiment_name);
+
+                    if fixated {
+                        let hwe = deme.model.hwe(&deme.eggs, &[]);
+                        report_genotypes(
+                            &deme.eggs,
+                            gen,
+                            Lifestage::Egg,
+                            &mut deme.outfile,
+                            false,
+                            None,
+                            &hwe,
+                        );
+                    } else {
+                        let hwe = deme.model.hwe(&deme.mature_adults, &deme.eggs_laid_hwe);
+                        report_genotypes(
+                            &deme.mature_adults,
+                            gen,
+                            Lifestage::Adult,
+                            &mut deme.outfile,
+                            false,
+                            None,
+                            &hwe,
+                        );
+                    }
+                }
+            }
+
+            if fixated {
+                break;
+            }
+        }
+
+        // Save the state of the run before the next generation
+        if writes_output
+            && params.checkpoint_interval > 0
+            && (gen + 1) % params.checkpoint_interval == 0
+            && gen < params.number_generations
+        {
+            let mut checkpoint = checkpoint::Writer::new(fingerprint, gen + 1);
+            outputs.save(&mut checkpoint);
+            checkpoint.rng(&rng);
+            checkpoint.value(next_id);
+            checkpoint.end_line();
+            summary.save(&mut checkpoint);
+
+            for deme in demes.iter() {
+                checkpoint.flies(&deme.eggs);
+            }
+
+            for tracker in drift.iter() {
+                tracker.save(&mut checkpoint);
+            }
+
+            if let Some(genealogy) = genealogy.as_ref() {
+                genealogy.save(&mut checkpoint);
+            }
+
+            if let Some(vcf_sampler) = vcf_sampler.as_ref() {
+                vcf_sampler.save(&mut checkpoint);
+            }
+
+            checkpoint.save(&checkpoint_path);
+        }
+    }
+
+    report_effective_size_summary(&mut ne_file, &drift);
+
+    if let Some(genealogy) = genealogy.as_ref() {
+        let mature_adults: Vec<Fly> = demes
+            .iter()
+            .flat_map(|d| d.mature_adults.iter().cloned())
+            .collect();
+        genealogy.write(
+            output_file,
+            &mature_adults,
+            params.tree_sequence_samples,
+            &mut rng,
+        );
+    }
+
+    if let Some(vcf_sampler) = vcf_sampler.as_mut() {
+        if vcf_sampler.samples_last_generation() {
+            vcf_sampler.sample(&deme_mature_adults(&demes), last_generation, &mut rng);
+        }
+
+        vcf_sampler.write(output_file, &demes[0].model.linkage);
+    }
+
+    if writes_output {
+        checkpoint::remove(&checkpoint_path);
+    }
+
+    summary
+}
@@ -0,0 +1,458 @@
+//// Parameter sweeps
+// The `sweep` subcommand varies parameters of a base parameter set, given
+// by the usual options, and writes one row per combination in the 30
+// column format of the parameter files in `02_info` used by `simulate`.
+// Each `--vary` spec is either a range, `male_success_bb=0.1:1.0:0.1`, or
+// a list, `environment_time=[7,10,30]`. Specs are combined as a full
+// factorial design or zipped together. Experiments are named from the
+// values of the varied parameters and can be run directly
+use clap::ArgMatches;
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::process;
+use std::str::FromStr;
+
+use crate::metapopulation::{Migration, MigrationModel, MigrationStage};
+use crate::parameters::Parameters;
+use crate::simulation::{self, OutputLayout};
+
+// Header and parameter of the columns following the ensemble, experiment
+// and changing parameters columns
+pub const TABLE_COLUMNS: [(&str, &str); 27] = [
+    ("numGenerations", "number_generations"),
+    ("eggsPerGenerations", "number_eggs_per_generation"),
+    ("eggsPerFemale", "number_eggs_per_female"),
+    ("proportionFemales", "proportion_females"),
+    ("propAA", "proportion_aa"),
+    ("propBB", "proportion_bb"),
+    ("globalSurvival", "survival_global"),
+    ("survivalFemalesAA", "survival_females_aa"),
+    ("survivalFemalesAB", "survival_females_ab"),
+    ("survivalFemalesBB", "survival_females_bb"),
+    ("survivalMalesAA", "survival_males_aa"),
+    ("survivalMalesAB", "survival_males_ab"),
+    ("survivalMalesBB", "survival_males_bb"),
+    ("reprodMalesAA", "male_success_aa"),
+    ("reprodMalesAB", "male_success_ab"),
+    ("reprodMalesBB", "male_success_bb"),
+    ("malesFreqDepCoef", "male_freq_dep_coef"),
+    ("eggsFemalesAA", "female_eggs_aa"),
+    ("eggsFemalesAB", "female_eggs_ab"),
+    ("eggsFemalesBB", "female_eggs_bb"),
+    ("femaleMaturation", "female_maturation_days"),
+    ("maleMaturationAA", "male_maturation_days_aa"),
+    ("maleMaturationAB", "male_maturation_days_ab"),
+    ("maleMaturationBB", "male_maturation_days_bb"),
+    ("maturationCV", "maturation_cv"),
+    ("environmentTime", "environment_time"),
+    ("environmentTimeVariation", "environment_time_variation"),
+];
+
+//// Enums
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Design {
+    Factorial,
+    Zip,
+}
+
+impl FromStr for Design {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "factorial" => Ok(Design::Factorial),
+            "zip" => Ok(Design::Zip),
+            _ => Err(format!("Unknown sweep design: {}", s)),
+        }
+    }
+}
+
+//// Structs
+#[derive(Clone, Debug)]
+pub struct SweepSpec {
+    pub name: String,
+    pub values: Vec<f64>,
+}
+
+impl FromStr for SweepSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Parse `name=start:end:step`, `name=[a,b,c]` or `name=value`
+        let mut parts = s.splitn(2, '=');
+        let name = parts.next().unwrap_or("").trim().to_string();
+        let spec = match parts.next() {
+            Some(spec) => spec.trim(),
+            None => return Err(format!("Sweep spec needs 'name=values': {}", s)),
+        };
+
+        if !TABLE_COLUMNS.iter().any(|(_, p)| *p == name) {
+            return Err(format!("Parameter cannot be swept: {}", name));
+        }
+
+        let parse = |v: &str| {
+            v.trim()
+                .parse::<f64>()
+                .map_err(|_| format!("Cannot parse sweep value '{}' in: {}", v, s))
+        };
+
+        let values = if spec.starts_with('[') && spec.ends_with(']') {
+            spec[1..spec.len() - 1]
+                .split(',')
+                .map(parse)
+                .collect::<Result<Vec<f64>, String>>()?
+        } else if spec.contains(':') {
+            let bounds = spec
+                .split(':')
+                .map(parse)
+                .collect::<Result<Vec<f64>, String>>()?;
+
+            if bounds.len() != 3 || bounds[2] <= 0.0 || bounds[1] < bounds[0] {
+                return Err(format!("Sweep range needs 'start:end:step': {}", s));
+            }
+
+            // Inclusive range, rounded to hide floating point steps
+            let number_values = ((bounds[1] - bounds[0]) / bounds[2] + 1e-9).floor() as usize + 1;
+
+            (0..number_values)
+                .map(|i| ((bounds[0] + i as f64 * bounds[2]) * 1e10).round() / 1e10)
+                .collect()
+        } else {
+            vec![parse(spec)?]
+        };
+
+        Ok(SweepSpec { name, values })
+    }
+}
+
+pub struct Experiment {
+    pub name: String,
+    pub params: Parameters,
+}
+
+//// Functions
+pub fn combinations(
+    specs: &[SweepSpec],
+    design: Design,
+) -> Result<Vec<Vec<(String, f64)>>, String> {
+    // Parameter values of each experiment
+    match design {
+        Design::Factorial => {
+            let mut rows: Vec<Vec<(String, f64)>> = vec![Vec::new()];
+
+            for spec in specs.iter() {
+                rows = rows
+                    .iter()
+                    .flat_map(|row| {
+                        spec.values.iter().map(move |v| {
+                            let mut row = row.clone();
+                            row.push((spec.name.clone(), *v));
+                            row
+                        })
+                    })
+                    .collect();
+            }
+
+            Ok(rows)
+        }
+
+        Design::Zip => {
+            let number_rows = specs.first().map(|s| s.values.len()).unwrap_or(1);
+
+            if specs.iter().any(|s| s.values.len() != number_rows) {
+                let lengths: Vec<String> = specs
+                    .iter()
+                    .map(|s| format!("{} has {}", s.name, s.values.len()))
+                    .collect();
+
+                return Err(format!(
+                    "Zipped sweep specs need the same number of values: {}",
+                    lengths.join(", ")
+                ));
+            }
+
+            Ok((0..number_rows)
+                .map(|i| {
+                    specs
+                        .iter()
+                        .map(|s| (s.name.clone(), s.values[i]))
+                        .collect()
+                })
+                .collect())
+        }
+    }
+}
+
+pub fn experiments(
+    base: &Parameters,
+    ensemble: &str,
+    specs: &[SweepSpec],
+    design: Design,
+) -> Result<Vec<Experiment>, String> {
+    // Parameters of each experiment, named from the varied values
+    let mut experiments = Vec::new();
+
+    for row in combinations(specs, design)? {
+        let mut params = base.clone();
+        let mut name = ensemble.to_string();
+
+        for (parameter, value) in row.iter() {
+            params.set(parameter, *value)?;
+            name.push_str(&format!("_{}_{}", parameter, value));
+        }
+
+        params.experiment_name = name.clone();
+        experiments.push(Experiment { name, params });
+    }
+
+    Ok(experiments)
+}
+
+pub fn write_table(
+    outfile: &mut dyn Write,
+    ensemble: &str,
+    specs: &[SweepSpec],
+    experiments: &[Experiment],
+) {
+    // Write experiments in the format of the parameter files
+    let mut header = String::from("#ensemble,experience,parametersChanging");
+
+    for (column, _) in TABLE_COLUMNS.iter() {
+        header.push(',');
+        header.push_str(column);
+    }
+
+    writeln!(outfile, "{}", header).expect("Cannot write to file");
+
+    let changing: Vec<&str> = specs.iter().map(|s| s.name.as_str()).collect();
+    let changing = changing.join("_");
+
+    for experiment in experiments.iter() {
+        let mut row = format!("{},{},{}", ensemble, experiment.name, changing);
+
+        for (_, parameter) in TABLE_COLUMNS.iter() {
+            row.push_str(&format!(",{}", experiment.params.get(parameter).unwrap()));
+        }
+
+        writeln!(outfile, "{}", row).expect("Cannot write to file");
+    }
+}
+
+pub fn run_experiments(
+    experiments: &[Experiment],
+    folder: &str,
+    ensemble: &str,
+    replicates: usize,
+) {
+    // Run each experiment like `simulate` does, with files named
+    // `folder/ensemble/experiment_replicate_N.csv`
+    let ensemble_folder = format!("{}/{}", folder, ensemble);
+    fs::create_dir_all(&ensemble_folder).expect("Cannot create output folder");
+    let width = replicates.to_string().len();
+
+    for experiment in experiments.iter() {
+        let demes_parameters = vec![experiment.params.clone()];
+        let migration = Migration::new(
+            MigrationModel::Island,
+            0.0,
+            0.0,
+            1,
+            None,
+            MigrationStage::Adult,
+        );
+
+        for replicate in 1..=replicates {
+            let output_file = format!(
+                "{}/{}_replicate_{:0width$}.csv",
+                ensemble_folder,
+                experiment.name,
+                replicate,
+                width = width
+            );
+
+            // Replicates of a seeded sweep each get their own seed
+            let mut params = experiment.params.clone();
+            params.seed = params.seed.map(|s| s.wrapping_add(replicate as u64));
+
+            simulation::run(
+                &params,
+                &demes_parameters,
+                &migration,
+                &output_file,
+                OutputLayout::PerDeme,
+            );
+        }
+    }
+}
+
+pub fn main(base: &Parameters, matches: &ArgMatches) {
+    // Build the experiments of a sweep, write their table and optionally
+    // run them
+    let ensemble = matches.value_of("ensemble").unwrap_or("sweep");
+
+    let design = matches
+        .value_of("design")
+        .unwrap_or("factorial")
+        .parse::<Design>()
+        .unwrap();
+
+    let specs: Vec<SweepSpec> = matches
+        .values_of("vary")
+        .map(|values| values.collect::<Vec<&str>>())
+        .unwrap_or_default()
+        .iter()
+        .map(|v| {
+            v.parse::<SweepSpec>().unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                process::exit(1);
+            })
+        })
+        .collect();
+
+    let experiments = experiments(base, ensemble, &specs, design).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+
+    match matches.value_of("table") {
+        Some(path) => {
+            let mut table = File::create(path).expect("Cannot creat file");
+            write_table(&mut table, ensemble, &specs, &experiments);
+        }
+        None => write_table(&mut io::stdout(), ensemble, &specs, &experiments),
+    }
+
+    let replicates = matches
+        .value_of("replicates")
+        .unwrap_or("0")
+        .parse::<usize>()
+        .unwrap();
+
+    if replicates > 0 {
+        let folder = matches
+            .value_of("output_folder")
+            .unwrap_or("03_simulations");
+        run_experiments(&experiments, folder, ensemble, replicates);
+    }
+}
+
+//// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(s: &str) -> SweepSpec {
+        s.parse::<SweepSpec>().unwrap()
+    }
+
+    #[test]
+    fn spec_ranges_include_their_end() {
+        let range = spec("male_success_bb=0.1:0.5:0.1");
+        assert_eq!(range.name, "male_success_bb");
+        assert_eq!(range.values, vec![0.1, 0.2, 0.3, 0.4, 0.5]);
+
+        let range = spec("environment_time=7:10:2");
+        assert_eq!(range.values, vec![7.0, 9.0]);
+    }
+
+    #[test]
+    fn spec_lists_and_single_values() {
+        let list = spec("environment_time=[7, 10,30]");
+        assert_eq!(list.name, "environment_time");
+        assert_eq!(list.values, vec![7.0, 10.0, 30.0]);
+
+        assert_eq!(spec("maturation_cv=0.25").values, vec![0.25]);
+    }
+
+    #[test]
+    fn malformed_specs_are_refused() {
+        for s in [
+            "male_success_bb",
+            "unknown_parameter=1",
+            "male_success_bb=0.1:1.0",
+            "male_success_bb=0.1:1.0:0",
+            "male_success_bb=1.0:0.1:0.1",
+            "male_success_bb=[0.1,high]",
+            "male_success_bb=low",
+        ]
+        .iter()
+        {
+            assert!(s.parse::<SweepSpec>().is_err(), "{} was accepted", s);
+        }
+    }
+
+    #[test]
+    fn factorial_combines_every_value() {
+        let specs = [
+            spec("male_success_bb=[0.1,0.2]"),
+            spec("environment_time=[7,10,30]"),
+        ];
+        let rows = combinations(&specs, Design::Factorial).unwrap();
+
+        assert_eq!(rows.len(), 6);
+        assert_eq!(
+            rows[0],
+            vec![
+                ("male_success_bb".to_string(), 0.1),
+                ("environment_time".to_string(), 7.0)
+            ]
+        );
+        assert_eq!(
+            rows[5],
+            vec![
+                ("male_success_bb".to_string(), 0.2),
+                ("environment_time".to_string(), 30.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn zip_pairs_values_in_order() {
+        let specs = [
+            spec("male_success_bb=0.1:0.3:0.1"),
+            spec("environment_time=[7,10,30]"),
+        ];
+        let rows = combinations(&specs, Design::Zip).unwrap();
+
+        assert_eq!(rows.len(), 3);
+        assert_eq!(
+            rows[1],
+            vec![
+                ("male_success_bb".to_string(), 0.2),
+                ("environment_time".to_string(), 10.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn table_joins_changing_parameters_with_underscores() {
+        let base = Parameters::from_matches(&clap::App::new("test").get_matches_from(vec!["test"]));
+        let specs = [spec("male_success_bb=[0.1]"), spec("environment_time=[7]")];
+        let experiments = experiments(&base, "62_sweep", &specs, Design::Factorial).unwrap();
+        let mut table = Vec::new();
+        write_table(&mut table, "62_sweep", &specs, &experiments);
+
+        let table = String::from_utf8(table).unwrap();
+        let row: Vec<&str> = table.lines().nth(1).unwrap().split(',').collect();
+        assert_eq!(row[0], "62_sweep");
+        assert_eq!(row[1], "62_sweep_male_success_bb_0.1_environment_time_7");
+        assert_eq!(row[2], "male_success_bb_environment_time");
+        assert_eq!(row.len(), 3 + TABLE_COLUMNS.len());
+    }
+
+    #[test]
+    fn zip_of_unequal_lengths_is_an_error() {
+        let specs = [
+            spec("male_success_bb=[0.1,0.2]"),
+            spec("environment_time=[7,10,30]"),
+        ];
+        let error = combinations(&specs, Design::Zip).unwrap_err();
+
+        assert_eq!(
+            error,
+            "Zipped sweep specs need the same number of values: \
+             male_success_bb has 2, environment_time has 3"
+        );
+    }
+}
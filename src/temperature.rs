@@ -0,0 +1,125 @@
+//// Temperature dependent development
+// With the `degree-days` development model, maturation requires a thermal
+// sum above a genotype specific threshold temperature. Maturation days of
+// each sex and genotype are the time needed at `reference_temperature`, so
+// the thermal sum is `days * (reference_temperature - threshold)` and the
+// time needed at temperature `t` is that sum divided by `t - threshold`.
+// Individuals never mature when the wrack is not warmer than their
+// threshold. The temperature of each generation is constant, read from a
+// schedule or drawn from a normal distribution
+use rand::distributions::{Distribution, Normal};
+
+use std::str::FromStr;
+
+use crate::demography;
+use crate::parameters::Parameters;
+use crate::random::SimulationRng;
+use crate::{genotype_index, Genotype};
+
+//// Enums
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum DevelopmentModel {
+    Days,
+    DegreeDays,
+}
+
+impl FromStr for DevelopmentModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "days" => Ok(DevelopmentModel::Days),
+            "degree-days" => Ok(DevelopmentModel::DegreeDays),
+            _ => Err(format!("Unknown development model: {}", s)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum TemperatureModel {
+    Constant,
+    Schedule,
+    Stochastic,
+}
+
+impl FromStr for TemperatureModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "constant" => Ok(TemperatureModel::Constant),
+            "schedule" => Ok(TemperatureModel::Schedule),
+            "stochastic" => Ok(TemperatureModel::Stochastic),
+            _ => Err(format!("Unknown temperature model: {}", s)),
+        }
+    }
+}
+
+//// Structs
+pub struct Development {
+    model: DevelopmentModel,
+    temperature_model: TemperatureModel,
+    temperature: f64,
+    temperature_sd: f64,
+    schedule: Vec<(u32, f64)>,
+    reference_temperature: f64,
+    thresholds: [f64; 3],
+}
+
+impl Development {
+    pub fn new(params: &Parameters) -> Development {
+        Development {
+            model: params.development_model,
+            temperature_model: params.temperature_model,
+            temperature: params.temperature,
+            temperature_sd: params.temperature_sd,
+            schedule: params.temperature_schedule.clone(),
+            reference_temperature: params.reference_temperature,
+            thresholds: [
+                params.development_threshold_aa,
+                params.development_threshold_ab,
+                params.development_threshold_bb,
+            ],
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.model == DevelopmentModel::DegreeDays
+    }
+
+    pub fn temperature_at(&self, generation: u32, rng: &mut SimulationRng) -> f64 {
+        match self.temperature_model {
+            TemperatureModel::Stochastic if self.temperature_sd > 0.0 => {
+                Normal::new(self.temperature, self.temperature_sd).sample(rng)
+            }
+
+            _ => self.mean_temperature_at(generation),
+        }
+    }
+
+    pub fn mean_temperature_at(&self, generation: u32) -> f64 {
+        match self.temperature_model {
+            TemperatureModel::Schedule => {
+                demography::scheduled_value(&self.schedule, generation, self.temperature)
+            }
+
+            TemperatureModel::Constant | TemperatureModel::Stochastic => self.temperature,
+        }
+    }
+
+    pub fn maturation(&self, days: f64, genotype: Genotype, temperature: f64) -> f64 {
+        // Days needed to mature at `temperature`, given the days needed at
+        // the reference temperature
+        if !self.is_active() {
+            return days;
+        }
+
+        let threshold = self.thresholds[genotype_index(genotype)];
+
+        if temperature <= threshold {
+            return f64::INFINITY;
+        }
+
+        days * (self.reference_temperature - threshold) / (temperature - threshold)
+    }
+}
@@ -0,0 +1,309 @@
+//// Tree sequence export
+// The genealogy of chromosomes at the inversion is recorded from the parent
+// chromosome copied by each chromosome of every adult, see `pedigree`.
+// Chromosomes leaving no descendants among adults of the next generation
+// are dropped as the simulation goes, along with ancestors left without
+// descendants. At the end, chromosomes of mature adults sampled from the
+// last generation are traced back and written as tskit text tables of
+// nodes, edges and individuals, loadable with
+// `tskit.load_text(nodes, edges, individuals=individuals, base64_metadata=False)`.
+// Node times are in generations before the last one and node metadata is
+// the arrangement carried. Edges span the whole sequence [0, 1) as they
+// follow the arrangement, not recombining neutral sites. Hemizygous males
+// under X linkage have a single node
+use rand::seq::SliceRandom;
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+
+use crate::checkpoint;
+use crate::neutral::chromosome_arrangements;
+use crate::random::SimulationRng;
+use crate::simulation::insert_before_extension;
+use crate::{Fly, Inheritance, Sex};
+
+//// Structs
+struct Chromosome {
+    parent: Option<(u64, u8)>,
+    generation: u32,
+    arrangement: char,
+    individual_parents: [u64; 2],
+    children: u32,
+}
+
+pub struct Genealogy {
+    x_linked: bool,
+    chromosomes: HashMap<(u64, u8), Chromosome>,
+    previous: Vec<(u64, u8)>,
+    current: Vec<(u64, u8)>,
+    last_generation: u32,
+}
+
+impl Genealogy {
+    pub fn new(inheritance: Inheritance) -> Genealogy {
+        Genealogy {
+            x_linked: inheritance == Inheritance::XLinked,
+            chromosomes: HashMap::new(),
+            previous: Vec::new(),
+            current: Vec::new(),
+            last_generation: 0,
+        }
+    }
+
+    fn number_chromosomes(&self, fly: &Fly) -> u8 {
+        if self.x_linked && fly.sex == Sex::Male {
+            1
+        } else {
+            2
+        }
+    }
+
+    fn parent_key(&self, origin: (u64, u8)) -> Option<(u64, u8)> {
+        // Hemizygous parents only have their first chromosome
+        if origin.0 == 0 {
+            None
+        } else if self.chromosomes.contains_key(&origin) {
+            Some(origin)
+        } else {
+            Some((origin.0, 0))
+        }
+    }
+
+    pub fn add_adults(&mut self, adults: &[Fly], generation: u32) {
+        for adult in adults.iter() {
+            let arrangements = chromosome_arrangements(adult.genotype);
+
+            for c in 0..self.number_chromosomes(adult) {
+                let parent = self.parent_key(adult.origins[c as usize]);
+
+                if let Some(parent) = parent {
+                    if let Some(p) = self.chromosomes.get_mut(&parent) {
+                        p.children += 1;
+                    }
+                }
+
+                self.chromosomes.insert(
+                    (adult.id, c),
+                    Chromosome {
+                        parent,
+                        generation,
+                        arrangement: arrangements[c as usize],
+                        individual_parents: adult.parents,
+                        children: 0,
+                    },
+                );
+                self.current.push((adult.id, c));
+            }
+        }
+
+        self.last_generation = generation;
+    }
+
+    pub fn end_generation(&mut self) {
+        // Drop chromosomes of the previous generation without children and
+        // then their ancestors left without any
+        let previous = std::mem::replace(&mut self.previous, std::mem::take(&mut self.current));
+
+        for key in previous.into_iter() {
+            let mut key = Some(key);
+
+            while let Some(k) = key {
+                key = match self.chromosomes.get(&k) {
+                    Some(c) if c.children == 0 => {
+                        let parent = c.parent;
+                        self.chromosomes.remove(&k);
+
+                        parent.filter(|p| match self.chromosomes.get_mut(p) {
+                            Some(p) => {
+                                p.children -= 1;
+                                true
+                            }
+                            None => false,
+                        })
+                    }
+                    _ => None,
+                };
+            }
+        }
+    }
+
+    pub fn save(&self, checkpoint: &mut checkpoint::Writer) {
+        checkpoint.value(self.chromosomes.len());
+        checkpoint.end_line();
+
+        for (key, c) in self.chromosomes.iter() {
+            checkpoint.value(key.0);
+            checkpoint.value(key.1);
+
+            match c.parent {
+                Some(parent) => {
+                    checkpoint.value(parent.0);
+                    checkpoint.value(parent.1);
+                }
+                None => checkpoint.none(),
+            }
+
+            checkpoint.value(c.generation);
+            checkpoint.value(c.arrangement);
+            checkpoint.value(c.individual_parents[0]);
+            checkpoint.value(c.individual_parents[1]);
+            checkpoint.value(c.children);
+            checkpoint.end_line();
+        }
+
+        for keys in [&self.previous, &self.current].iter() {
+            checkpoint.value(keys.len());
+
+            for key in keys.iter() {
+                checkpoint.value(key.0);
+                checkpoint.value(key.1);
+            }
+
+            checkpoint.end_line();
+        }
+
+        checkpoint.value(self.last_generation);
+        checkpoint.end_line();
+    }
+
+    pub fn restore(&mut self, checkpoint: &mut checkpoint::Reader) {
+        let number: usize = checkpoint.value();
+        self.chromosomes = HashMap::with_capacity(number);
+
+        for _ in 0..number {
+            let key = (checkpoint.value(), checkpoint.value());
+            let parent = if checkpoint.none() {
+                None
+            } else {
+                Some((checkpoint.value(), checkpoint.value()))
+            };
+
+            self.chromosomes.insert(
+                key,
+                Chromosome {
+                    parent,
+                    generation: checkpoint.value(),
+                    arrangement: checkpoint.value(),
+                    individual_parents: [checkpoint.value(), checkpoint.value()],
+                    children: checkpoint.value(),
+                },
+            );
+        }
+
+        let mut keys = || {
+            let number: usize = checkpoint.value();
+            (0..number)
+                .map(|_| (checkpoint.value(), checkpoint.value()))
+                .collect()
+        };
+        self.previous = keys();
+        self.current = keys();
+        self.last_generation = checkpoint.value();
+    }
+
+    pub fn write(
+        &self,
+        output_file: &str,
+        mature_adults: &[Fly],
+        number_samples: usize,
+        rng: &mut SimulationRng,
+    ) {
+        // Sample mature adults, all of them when `number_samples` is 0
+        let samples: Vec<&Fly> = if number_samples == 0 || number_samples >= mature_adults.len() {
+            mature_adults.iter().collect()
+        } else {
+            mature_adults.choose_multiple(rng, number_samples).collect()
+        };
+
+        let mut nodes: HashMap<(u64, u8), usize> = HashMap::new();
+        let mut node_rows: Vec<(bool, (u64, u8))> = Vec::new();
+        let mut edge_rows: Vec<(usize, usize)> = Vec::new();
+
+        for sample in samples.iter() {
+            for c in 0..self.number_chromosomes(sample) {
+                nodes.insert((sample.id, c), node_rows.len());
+                node_rows.push((true, (sample.id, c)));
+            }
+        }
+
+        // Trace each lineage back until it joins a recorded one
+        for i in 0..node_rows.len() {
+            let mut child = node_rows[i].1;
+
+            while let Some(parent) = self.chromosomes.get(&child).and_then(|c| c.parent) {
+                let known = nodes.contains_key(&parent);
+
+                if !known {
+                    nodes.insert(parent, node_rows.len());
+                    node_rows.push((false, parent));
+                }
+
+                edge_rows.push((nodes[&parent], nodes[&child]));
+
+                if known {
+                    break;
+                }
+
+                child = parent;
+            }
+        }
+
+        // One individual per fly with nodes
+        let mut individuals: HashMap<u64, usize> = HashMap::new();
+        let mut individual_rows: Vec<[u64; 2]> = Vec::new();
+
+        for (_, key) in node_rows.iter() {
+            if let Entry::Vacant(e) = individuals.entry(key.0) {
+                e.insert(individual_rows.len());
+                individual_rows.push(self.chromosomes[key].individual_parents);
+            }
+        }
+
+        let mut nodes_file = File::create(insert_before_extension(output_file, "_nodes"))
+            .expect("Cannot creat file");
+        writeln!(nodes_file, "id\tis_sample\ttime\tindividual\tmetadata")
+            .expect("Cannot write to file");
+
+        for (i, (is_sample, key)) in node_rows.iter().enumerate() {
+            let chromosome = &self.chromosomes[key];
+            writeln!(
+                nodes_file,
+                "{}\t{}\t{}\t{}\t{}",
+                i,
+                *is_sample as u8,
+                self.last_generation - chromosome.generation,
+                individuals[&key.0],
+                chromosome.arrangement
+            )
+            .expect("Cannot write to file");
+        }
+
+        let mut edges_file = File::create(insert_before_extension(output_file, "_edges"))
+            .expect("Cannot creat file");
+        writeln!(edges_file, "left\tright\tparent\tchild").expect("Cannot write to file");
+
+        for (parent, child) in edge_rows.iter() {
+            writeln!(edges_file, "0\t1\t{}\t{}", parent, child).expect("Cannot write to file");
+        }
+
+        let mut individuals_file =
+            File::create(insert_before_extension(output_file, "_individuals"))
+                .expect("Cannot creat file");
+        writeln!(individuals_file, "id\tflags\tlocation\tparents").expect("Cannot write to file");
+
+        for (i, individual_parents) in individual_rows.iter().enumerate() {
+            let parents: Vec<String> = individual_parents
+                .iter()
+                .map(|p| match individuals.get(p) {
+                    Some(j) => j.to_string(),
+                    None => "-1".to_string(),
+                })
+                .collect();
+
+            writeln!(individuals_file, "{}\t0\t\t{}", i, parents.join(","))
+                .expect("Cannot write to file");
+        }
+    }
+}
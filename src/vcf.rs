@@ -0,0 +1,166 @@
+//// VCF samples
+// Mature adults sampled at chosen generations, the last one by default, are
+// written to a VCF file ending in `_samples.vcf` with their phased
+// genotypes at the inversion and at neutral sites. Sample names give the
+// generation, deme, sex and identifier of each fly, as in
+// `G200_D1_female_5123`. The chromosome of length 1.0 of the model is
+// scaled to `CHROMOSOME_LENGTH` base pairs. The inversion is a symbolic
+// `<INV>` allele spanning `inversion_start` to `inversion_end`, B being the
+// alternate arrangement, and neutral sites have arbitrary `A` and `T`
+// alleles for their ancestral and derived states. Hemizygous males under X
+// linkage have haploid genotypes
+use rand::seq::SliceRandom;
+
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use crate::checkpoint;
+use crate::neutral::{chromosome_arrangements, Linkage};
+use crate::parameters::Parameters;
+use crate::random::SimulationRng;
+use crate::simulation::insert_before_extension;
+use crate::Fly;
+
+const CHROMOSOME_LENGTH: f64 = 1_000_000.0;
+const CHROMOSOME: &str = "1";
+
+//// Structs
+pub struct VcfSampler {
+    number_samples: usize,
+    generations: Vec<u32>,
+    inversion_start: f64,
+    inversion_end: f64,
+    samples: Vec<(String, Fly)>,
+}
+
+impl VcfSampler {
+    pub fn new(params: &Parameters) -> VcfSampler {
+        VcfSampler {
+            number_samples: params.vcf_samples,
+            generations: params.vcf_generations.clone(),
+            inversion_start: params.inversion_start,
+            inversion_end: params.inversion_end,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn samples_generation(&self, generation: u32) -> bool {
+        self.generations.contains(&generation)
+    }
+
+    pub fn samples_last_generation(&self) -> bool {
+        self.generations.is_empty()
+    }
+
+    pub fn sample(
+        &mut self,
+        mature_adults: &[(usize, Fly)],
+        generation: u32,
+        rng: &mut SimulationRng,
+    ) {
+        // Sample mature adults of all demes, given with their deme
+        let number_samples = self.number_samples.min(mature_adults.len());
+
+        for (deme, fly) in mature_adults.choose_multiple(rng, number_samples) {
+            let name = format!("G{}_D{}_{}_{}", generation, deme + 1, fly.sex, fly.id);
+            self.samples.push((name, *fly));
+        }
+    }
+
+    pub fn save(&self, checkpoint: &mut checkpoint::Writer) {
+        checkpoint.value(self.samples.len());
+        checkpoint.end_line();
+
+        for (name, fly) in self.samples.iter() {
+            checkpoint.value(name);
+            checkpoint.fly(fly);
+        }
+    }
+
+    pub fn restore(&mut self, checkpoint: &mut checkpoint::Reader) {
+        let number: usize = checkpoint.value();
+        self.samples = (0..number)
+            .map(|_| (checkpoint.value(), checkpoint.fly()))
+            .collect();
+    }
+
+    pub fn write(&self, output_file: &str, linkage: &Linkage) {
+        let path =
+            Path::new(&insert_before_extension(output_file, "_samples")).with_extension("vcf");
+        let mut vcf_file = File::create(path).expect("Cannot creat file");
+
+        let position = |p: f64| (p * CHROMOSOME_LENGTH) as u64 + 1;
+
+        // Header
+        let names: Vec<&str> = self.samples.iter().map(|(n, _)| n.as_str()).collect();
+        writeln!(
+            vcf_file,
+            "##fileformat=VCFv4.2\n\
+             ##source=coelopa_fastsim\n\
+             ##contig=<ID={},length={}>\n\
+             ##ALT=<ID=INV,Description=\"Inversion, arrangement B\">\n\
+             ##INFO=<ID=SVTYPE,Number=1,Type=String,Description=\"Type of structural variant\">\n\
+             ##INFO=<ID=END,Number=1,Type=Integer,Description=\"End position of the variant\">\n\
+             ##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">\n\
+             #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\t{}",
+            CHROMOSOME,
+            CHROMOSOME_LENGTH as u64,
+            names.join("\t")
+        )
+        .expect("Cannot write to file");
+
+        // Records ordered by position, the inversion coming before neutral
+        // sites at its start
+        let mut records: Vec<(u64, Option<usize>)> = vec![(position(self.inversion_start), None)];
+
+        if linkage.is_active() {
+            for (i, p) in linkage.positions().iter().enumerate() {
+                records.push((position(*p), Some(i)));
+            }
+        }
+
+        records.sort_by_key(|(p, site)| (*p, site.is_some()));
+
+        for (pos, site) in records.iter() {
+            let mut row = match site {
+                None => format!(
+                    "{}\t{}\tinversion\tN\t<INV>\t.\tPASS\tSVTYPE=INV;END={}\tGT",
+                    CHROMOSOME,
+                    pos,
+                    position(self.inversion_end)
+                ),
+                Some(i) => format!(
+                    "{}\t{}\tsite_{}\tA\tT\t.\tPASS\t.\tGT",
+                    CHROMOSOME,
+                    pos,
+                    i + 1
+                ),
+            };
+
+            for (_, fly) in self.samples.iter() {
+                let arrangements = chromosome_arrangements(fly.genotype);
+                let alleles: Vec<&str> = (0..2)
+                    .take(if linkage.hemizygous(fly) { 1 } else { 2 })
+                    .map(|c| {
+                        let alternate = match site {
+                            None => arrangements[c] == 'B',
+                            Some(i) => fly.haplotypes[c] & (1 << i) != 0,
+                        };
+
+                        if alternate {
+                            "1"
+                        } else {
+                            "0"
+                        }
+                    })
+                    .collect();
+
+                row.push('\t');
+                row.push_str(&alleles.join("|"));
+            }
+
+            writeln!(vcf_file, "{}", row).expect("Cannot write to file");
+        }
+    }
+}
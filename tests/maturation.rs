@@ -0,0 +1,87 @@
+#![allow(clippy::four_forward_slashes)]
+//// Maturation time edge cases
+// Effects shortening maturation to zero days or less must let flies mature
+// at once instead of stopping the run, and invalid effects must be refused
+// with an error
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+//// Functions
+fn simulate(name: &str, extra: &[&str]) -> (Output, PathBuf) {
+    let folder =
+        std::env::temp_dir().join(format!("coelopa_fastsim_maturation_{}", std::process::id()));
+    fs::create_dir_all(&folder).unwrap();
+    let output_file = folder.join(format!("{}.csv", name));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_coelopa_fastsim"))
+        .arg("--output-file")
+        .arg(&output_file)
+        .args([
+            "--seed",
+            "1",
+            "--number-generations",
+            "5",
+            "--number-eggs-per-generation",
+            "2000",
+            "--quiet",
+        ])
+        .args(extra)
+        .output()
+        .expect("Cannot run coelopa_fastsim");
+
+    (output, output_file)
+}
+
+fn assert_completes(name: &str, extra: &[&str]) {
+    let (output, output_file) = simulate(name, extra);
+    assert!(
+        output.status.success(),
+        "{} failed: {}",
+        name,
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // Header and one line per generation, from generation 0
+    let content = fs::read_to_string(&output_file).unwrap();
+    assert_eq!(content.lines().count(), 7, "{} stopped early", name);
+    fs::remove_file(&output_file).unwrap();
+}
+
+#[test]
+fn negative_maternal_offsets_mature_at_once() {
+    assert_completes("carryover", &["--carryover-maturation=-10"]);
+    assert_completes(
+        "maternal",
+        &["--maternal-maturation-aa=-20", "--proportion-aa", "0.5"],
+    );
+}
+
+#[test]
+fn invalid_maternal_effects_are_refused() {
+    let (output, _) = simulate("survival", &["--maternal-survival-ab=-1"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Maternal survivals"));
+
+    let (output, _) = simulate("offset", &["--carryover-maturation", "NaN"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("must be finite"));
+}
+
+#[test]
+fn size_maturation_must_stay_positive() {
+    let (output, _) = simulate(
+        "intercept",
+        &["--size-model", "trait", "--size-maturation-intercept=-5"],
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("positive intercept"));
+
+    let (output, _) = simulate(
+        "slope",
+        &["--size-model", "trait", "--size-maturation-slope=-1"],
+    );
+    assert!(!output.status.success());
+
+    assert_completes("size", &["--size-model", "trait"]);
+}
@@ -0,0 +1,116 @@
+#![allow(clippy::four_forward_slashes)]
+//// Resuming from checkpoints
+// A seeded run killed after a checkpoint, with partial lines written past
+// it, and continued with `--resume` must give the same files, byte for
+// byte, as the same run done in one go. Completed runs leave no checkpoint
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::process::{Child, Command};
+use std::thread;
+use std::time::Duration;
+
+//// Functions
+fn command(folder: &Path, extra: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_coelopa_fastsim"));
+    command
+        .arg("--output-file")
+        .arg(folder.join("run.csv"))
+        .args([
+            "--seed",
+            "11",
+            "--number-generations",
+            "30",
+            "--number-eggs-per-generation",
+            "2000",
+            "--number-demes",
+            "2",
+            "--migration-rate",
+            "0.05",
+            "--neutral-sites",
+            "20",
+            "--effective-size",
+            "--pedigree",
+            "--quiet",
+        ])
+        .args(extra);
+    command
+}
+
+fn simulate(folder: &Path, extra: &[&str]) {
+    let status = command(folder, extra)
+        .status()
+        .expect("Cannot run coelopa_fastsim");
+
+    assert!(status.success());
+}
+
+fn interrupt(mut child: Child, checkpoint: &Path) {
+    // Kill a run as soon as it saves its first checkpoint, which appears
+    // complete as it is renamed into place
+    while !checkpoint.exists() {
+        if child.try_wait().unwrap().is_some() {
+            panic!("Run completed before its first checkpoint");
+        }
+
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
+
+fn output_files(folder: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(folder)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name.ends_with(".csv"))
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn resumed_run_matches_uninterrupted_run() {
+    let folder =
+        std::env::temp_dir().join(format!("coelopa_fastsim_resume_{}", std::process::id()));
+    let uninterrupted = folder.join("uninterrupted");
+    let resumed = folder.join("resumed");
+    let checkpoint = resumed.join("run.checkpoint");
+    fs::create_dir_all(&uninterrupted).unwrap();
+    fs::create_dir_all(&resumed).unwrap();
+
+    simulate(&uninterrupted, &["--checkpoint-interval", "7"]);
+    assert!(!uninterrupted.join("run.checkpoint").exists());
+
+    // The run is killed after its checkpoint at generation 7, in the middle
+    // of writing the next generations
+    let child = command(&resumed, &["--checkpoint-interval", "7"])
+        .spawn()
+        .expect("Cannot run coelopa_fastsim");
+    interrupt(child, &checkpoint);
+    assert!(checkpoint.exists());
+
+    for name in output_files(&resumed).iter() {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(resumed.join(name))
+            .unwrap();
+        file.write_all(b"29,interrupted").unwrap();
+    }
+
+    simulate(&resumed, &["--checkpoint-interval", "7", "--resume"]);
+    assert!(!checkpoint.exists());
+
+    let names = output_files(&uninterrupted);
+    assert_eq!(names, output_files(&resumed));
+    assert!(names.len() > 1);
+
+    for name in names.iter() {
+        let expected = fs::read(uninterrupted.join(name)).unwrap();
+        let obtained = fs::read(resumed.join(name)).unwrap();
+        assert!(expected == obtained, "{} differs after resuming", name);
+    }
+
+    fs::remove_dir_all(&folder).unwrap();
+}